@@ -1,6 +1,55 @@
 void* malloc(int size);
 void free(void* addr);
 
+// system calls, see syscalls.rs for their numbers & the registers their arguments are passed in
+int syscall(int num, int arg1, int arg2, int arg3){
+    int* SA1 = 206;
+    int* SA2 = 207;
+    int* SA3 = 208;
+    int* SYS = 205;
+    int* SRV = 209;
+    *SA1 = arg1;
+    *SA2 = arg2;
+    *SA3 = arg3;
+    *SYS = num;
+    return *SRV;
+}
+
+void exit(int status){
+    syscall(1, status, 0, 0);
+}
+
+int read(int fd, char* buf, int n){
+    return syscall(2, fd, buf, n);
+}
+
+int write(int fd, char* buf, int n){
+    return syscall(3, fd, buf, n);
+}
+
+void* sbrk(int n){
+    return syscall(4, n, 0, 0);
+}
+
+// flags are POSIX's, e.g O_WRONLY | O_CREAT = 1 | 64, see filesystem.rs
+int open(char* path, int flags){
+    return syscall(5, path, flags, 0);
+}
+
+int close(int fd){
+    return syscall(6, fd, 0, 0);
+}
+
+int lseek(int fd, int offset, int whence){
+    return syscall(7, fd, offset, whence);
+}
+
+int unlink(char* path){
+    return syscall(8, path, 0, 0);
+}
+
+// programs may define their own putc, e.g to redirect output
+#pragma weak putc
 void putc(char c){
     int* COS = 200;
     int* COD = 201;
@@ -8,13 +57,26 @@ void putc(char c){
     *COS = 1;
 }
 
+// lets the next process run
+void yield(){
+    int* YLD = 204;
+    *YLD = 1;
+}
+
+int strlen(char* str){
+    int len = 0;
+    for(; *str != 0; str++, len++) {
+    }
+    return len;
+}
+
 void puts(char* str){
     while(*str != 0){
         putc(*(str++));
     }
 }
 
-void revstr(char* str, int start, int end){
+static void revstr(char* str, int start, int end){
     end -= 1;
     for(; start < end; start++, end--){
         char* c1 = str + start;
@@ -61,14 +123,19 @@ struct FreeBlock* free_root;
 
 
 void malloc_init(){
-    // yo we need #define
-    int HEAP_START = 4000;
-    int HEAP_END = 6000;
-    free_root = (struct FreeBlock*) (HEAP_START);
+    // malloc manages the whole heap, which it takes from sbrk, the OS's layout registers tell where the heap ends
+    int* HEAP_END = 215;
+    int heap_start = sbrk(0);
+    int heap_size = *HEAP_END - heap_start;
+    // another process owns the heap, malloc returns NULL
+    if (sbrk(heap_size) == -1) {
+        return;
+    }
+    free_root = (struct FreeBlock*) (heap_start);
     free_root->next_free = 0;
     free_root->prev_free = 0;
-    free_root->start = (void*) (HEAP_START);
-    free_root->size = HEAP_END - HEAP_START;
+    free_root->start = (void*) (heap_start);
+    free_root->size = heap_size;
     // puts(itos(free_root->size));
 }
 
@@ -122,3 +189,84 @@ void free(void* addr){
     prev_root->prev_free = addr_block;
     free_root = addr_block;
 }
+
+// buffered files aren't supported, a FILE only holds its file descriptor
+struct FILE {
+    int fd;
+};
+
+// mode is "r", "w" or "a", optionally followed by '+'
+struct FILE* fopen(char* path, char* mode){
+    int flags = 0;
+    if (*mode == 'w') {
+        flags = 1 | 64 | 512;
+    }
+    if (*mode == 'a') {
+        flags = 1 | 64 | 1024;
+    }
+    if (*(mode + 1) == '+') {
+        flags = flags - (flags & 3) + 2;
+    }
+    int fd = open(path, flags);
+    if (fd < 0) {
+        return 0;
+    }
+    struct FILE* file = malloc(sizeof(struct FILE));
+    file->fd = fd;
+    return file;
+}
+
+int fclose(struct FILE* file){
+    int res = close(file->fd);
+    free(file);
+    return res;
+}
+
+// reads a line, up to n - 1 chars, returns 0 at the end of the file
+char* fgets(char* buf, int n, struct FILE* file){
+    char* cur = buf;
+    char c = 0;
+    for(int count = 0; count < n - 1 && c != '\n'; count++) {
+        if (read(file->fd, &c, 1) != 1) {
+            break;
+        }
+        *cur = c;
+        cur++;
+    }
+    *cur = 0;
+    if (cur == buf) {
+        return 0;
+    }
+    return buf;
+}
+
+int fputs(char* str, struct FILE* file){
+    return write(file->fd, str, strlen(str));
+}
+
+// supports a single argument, formatted with %d, %s or %c, & %%
+int fprintf(struct FILE* file, char* format, int arg){
+    int count = 0;
+    for(; *format != 0; format++) {
+        char c = *format;
+        if (c == '%') {
+            format++;
+            c = *format;
+            if (c == 'd') {
+                char* num = itos(arg);
+                count += fputs(num, file);
+                free(num);
+                continue;
+            }
+            if (c == 's') {
+                count += fputs(arg, file);
+                continue;
+            }
+            if (c == 'c') {
+                c = arg;
+            }
+        }
+        count += write(file->fd, &c, 1);
+    }
+    return count;
+}
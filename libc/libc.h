@@ -1,5 +1,23 @@
 void putc(char c);
 void puts(char* str);
+void yield();
+void exit(int status);
+int read(int fd, char* buf, int n);
+int write(int fd, char* buf, int n);
+void* sbrk(int n);
+int open(char* path, int flags);
+int close(int fd);
+int lseek(int fd, int offset, int whence);
+int unlink(char* path);
 void* malloc(int size);
 void free(void* addr);
-char* itos(int num);
\ No newline at end of file
+char* itos(int num);
+int strlen(char* str);
+struct FILE {
+    int fd;
+};
+struct FILE* fopen(char* path, char* mode);
+int fclose(struct FILE* file);
+char* fgets(char* buf, int n, struct FILE* file);
+int fputs(char* str, struct FILE* file);
+int fprintf(struct FILE* file, char* format, int arg);
\ No newline at end of file
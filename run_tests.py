@@ -16,7 +16,7 @@ COMPILER_TESTS_FILE = 'tests/generated_compiler_tests.rs'
 def get_test_categories():
     return {
         category: {
-            'inputs': sorted(listdir(path.join(TESTS_DIR, category, 'inputs'))),
+            'inputs': sorted(inp for inp in listdir(path.join(TESTS_DIR, category, 'inputs')) if inp.endswith('.c')),
         } for category in listdir(TESTS_DIR) if not category.startswith('_')
     }
 
@@ -33,20 +33,26 @@ compiler_tests_code = """
 // some of the test cases are from: https://github.com/nlsandler/write_a_c_compiler/
 
 extern crate simple_vm;
-use simple_vm::operating_system::OS;
+use simple_vm::operating_system::assembler::format_errors;
 use simple_vm::operating_system::compiler::Compiler;
+use simple_vm::operating_system::layout::MemoryLayout;
+use simple_vm::operating_system::run::RunLimits;
+use simple_vm::operating_system::streams::{Input, Output};
+use simple_vm::operating_system::OS;
 
-use std::io;
-use std::fs::{self, DirEntry};
-use std::path::Path;
 use std::fs::File;
+use std::fs::{self, DirEntry};
+use std::io;
 use std::io::prelude::*;
 use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug)]
 struct CompilerTestCase{
     category: String,
     input_f: String,
+    stdin_f: Option<String>,
     target_res_f : Option<String>,
     target_out_f : Option<String>,
 }
@@ -59,10 +65,17 @@ fn read_file_content(file_path: &str) -> String{
 }
 
 fn test_single(test_case: &CompilerTestCase){
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
+    // the program's stdin is the case's .in file, if it has one, & its stdout is captured
+    os.stdin = Input::buffer(test_case.stdin_f.as_ref().map_or(String::new(), |stdin_f| read_file_content(stdin_f)));
+    os.stdout = Output::buffer();
+    // a program that doesn't end fails its test instead of hanging
+    os.limits = RunLimits { max_instructions: Some(10_000_000), timeout: Some(Duration::from_secs(30)) };
     let program = os.compile(&test_case.input_f);
-    let res = os.assemble_and_run(&program);
-    let out = &os.out_chars.iter().map(|c| c.to_string()).collect::<Vec<String>>().join("");
+    let exec = os.link_with_std(vec![&program]).unwrap_or_else(|errors| panic!("assembly failed:\n{}", format_errors(&errors)));
+    let outcome = os.run(&exec);
+    let res = outcome.exit_status().unwrap_or_else(|| panic!("the program didn't exit, {}", outcome.status));
+    let out = String::from_utf8_lossy(&outcome.stdout);
     if let Some(res_f) = &test_case.target_res_f {
         let tar_res = read_file_content(res_f);
         println!("res: {},{}", res.to_string(), tar_res);
@@ -81,6 +94,8 @@ for cat, case in zip(categories, cases):
     target_out_fpath = path.join(TESTS_DIR, cat, 'targets', case + '.out')
     assert any((path.isfile(target_res_fpath), path.isfile(target_out_fpath))), f"{cat}/{case} doesn't have any targets files"
     target_res_f = f"""Some("{target_res_fpath}".to_string()) """ if path.isfile(target_res_fpath) else "None"
+    stdin_fpath = path.join(TESTS_DIR, cat, 'inputs', case + '.in')
+    stdin_f = f"""Some("{stdin_fpath}".to_string()) """ if path.isfile(stdin_fpath) else "None"
     target_out_f = f"""Some("{target_out_fpath}".to_string()) """ if path.isfile(target_out_fpath) else "None"
     compiler_tests_code += \
     f"""
@@ -89,6 +104,7 @@ fn test_{cat}_{case}(){{
     let case = CompilerTestCase{{
         category: "{cat}".to_string(),
         input_f: "{path.join(TESTS_DIR, cat, 'inputs', case + ".c")}".to_string(),
+        stdin_f: {stdin_f},
         target_res_f: {target_res_f},
         target_out_f: {target_out_f},
     }};
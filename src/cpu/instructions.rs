@@ -41,6 +41,8 @@ impl std::fmt::Display for Register {
 }
 
 impl Register {
+    pub const ALL: [Register; 8] = [Register::R1, Register::R2, Register::R3, Register::R4, Register::SP, Register::BP, Register::IR, Register::ZR];
+
     pub fn to_str(&self) -> String {
         format!(
             "{}",
@@ -272,6 +274,13 @@ impl RegOrImm {
         x.evaluate()
     }
 
+    pub fn to_str(&self) -> String {
+        match self {
+            RegOrImm::Reg(reg) => reg.to_str(),
+            RegOrImm::Val(val) => val.to_string(),
+        }
+    }
+
     fn from_str(s: &str) -> Result<RegOrImm, ()> {
         if let Ok(reg) = Register::from_str(s) {
             Ok(RegOrImm::from(reg))
@@ -321,6 +330,7 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    /// returns the instruction in assembly syntax, such that from_str(to_str()) gives back the same instruction
     pub fn to_str(&self) -> String {
         match &self {
             Instruction::UnaryArith { op, arg } => format!("{:?} {:?}", op, arg),
@@ -329,67 +339,125 @@ impl Instruction {
                 dst,
                 arg1,
                 arg2,
-            } => format!("{:?} {:?} {:?} {:?}", op, dst, arg1, arg2),
-            Instruction::Data { op, dst, src } => format!("{:?} {:?} {:?}", op, dst, src),
+            } => format!("{:?} {:?} {:?} {}", op, dst, arg1, arg2.to_str()),
+            Instruction::Data { op, dst, src } => format!("{:?} {:?} {}", op, dst, src.to_str()),
             Instruction::Stack { op, dst } => format!("{:?} {:?}", op, dst),
-            Instruction::Test { op, arg1, arg2 } => format!("{:?} {:?} {:?}", op, arg1, arg2),
+            Instruction::Test { op, arg1, arg2 } => format!("{:?} {:?} {}", op, arg1, arg2.to_str()),
             Instruction::Flow { op, offset } => format!("{:?} {:?}", op, offset),
             Instruction::Other { op } => format!("{:?}", op),
         }
     }
 
-    pub fn from_str(instruction_str: &str) -> Result<Instruction, ()> {
+    pub fn from_str(instruction_str: &str) -> Result<Instruction, InstructionParseError> {
         let args: Vec<&str> = instruction_str.split_whitespace().collect();
+        if args.is_empty() {
+            return Err(InstructionParseError::new(0, ParseErrorKind::Empty));
+        }
         let op = args[0];
 
         if let Result::Ok(op) = UnaryArithOp::from_str(&op) {
-            assert!(args.len() == 2);
+            expect_operands(&args, 1)?;
             return Ok(Instruction::UnaryArith {
                 op: op,
-                arg: Register::from_str(args[1]).unwrap(),
+                arg: parse_register(&args, 1)?,
             });
         } else if let Result::Ok(op) = BinArithOp::from_str(&op) {
-            assert!(args.len() == 4);
+            expect_operands(&args, 3)?;
             return Ok(Instruction::BinArith {
                 op: op,
-                dst: Register::from_str(args[1]).unwrap(),
-                arg1: Register::from_str(args[2]).unwrap(),
-                arg2: RegOrImm::from_str(args[3]).unwrap(),
+                dst: parse_register(&args, 1)?,
+                arg1: parse_register(&args, 2)?,
+                arg2: parse_reg_or_imm(&args, 3)?,
             });
         } else if let Result::Ok(op) = DataOp::from_str(&op) {
-            assert!(args.len() == 3);
+            expect_operands(&args, 2)?;
             return Ok(Instruction::Data {
                 op: op,
-                dst: Register::from_str(args[1]).unwrap(),
-                src: RegOrImm::from_str(args[2]).unwrap(),
+                dst: parse_register(&args, 1)?,
+                src: parse_reg_or_imm(&args, 2)?,
             });
         } else if let Result::Ok(op) = StackOp::from_str(&op) {
-            assert!(args.len() == 2);
+            expect_operands(&args, 1)?;
             return Ok(Instruction::Stack {
                 op: op,
-                dst: Register::from_str(args[1]).unwrap(),
+                dst: parse_register(&args, 1)?,
             });
         } else if let Result::Ok(op) = TestOp::from_str(&op) {
-            assert!(args.len() == 3);
+            expect_operands(&args, 2)?;
             return Ok(Instruction::Test {
                 op: op,
-                arg1: Register::from_str(args[1]).unwrap(),
-                arg2: RegOrImm::from_str(args[2]).unwrap(),
+                arg1: parse_register(&args, 1)?,
+                arg2: parse_reg_or_imm(&args, 2)?,
             });
         } else if let Result::Ok(op) = FlowOp::from_str(&op) {
-            assert!(args.len() == 2);
+            expect_operands(&args, 1)?;
             return Ok(Instruction::Flow {
                 op: op,
-                offset: args[1].parse::<i32>().unwrap(),
+                offset: args[1].parse::<i32>().map_err(|_| InstructionParseError::new(1, ParseErrorKind::BadImmediate(args[1].to_string())))?,
             });
         } else if let Result::Ok(op) = OtherOp::from_str(&op) {
-            assert!(args.len() == 1);
+            expect_operands(&args, 0)?;
             return Ok(Instruction::Other { op: op });
         }
-        Err(())
+        Err(InstructionParseError::new(0, ParseErrorKind::UnknownMnemonic(op.to_string())))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseErrorKind {
+    Empty,
+    UnknownMnemonic(String),
+    WrongOperandCount { expected: usize, found: usize },
+    BadRegister(String),
+    BadImmediate(String),
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Empty => write!(f, "empty instruction"),
+            ParseErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic '{}'", m),
+            ParseErrorKind::WrongOperandCount { expected, found } => {
+                write!(f, "wrong operand count: expected {}, found {}", expected, found)
+            }
+            ParseErrorKind::BadRegister(r) => write!(f, "bad register '{}'", r),
+            ParseErrorKind::BadImmediate(v) => write!(f, "bad operand '{}'", v),
+        }
+    }
+}
+
+/// error returned by Instruction::from_str
+/// token is the index of the offending whitespace separated token (0 is the mnemonic)
+#[derive(Debug, PartialEq, Clone)]
+pub struct InstructionParseError {
+    pub token: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl InstructionParseError {
+    pub fn new(token: usize, kind: ParseErrorKind) -> InstructionParseError {
+        InstructionParseError { token, kind }
     }
 }
 
+fn expect_operands(args: &[&str], expected: usize) -> Result<(), InstructionParseError> {
+    let found = args.len() - 1;
+    if found != expected {
+        // point at the first extra operand, or at the mnemonic if operands are missing
+        let token = if found > expected { expected + 1 } else { 0 };
+        return Err(InstructionParseError::new(token, ParseErrorKind::WrongOperandCount { expected, found }));
+    }
+    Ok(())
+}
+
+fn parse_register(args: &[&str], i: usize) -> Result<Register, InstructionParseError> {
+    Register::from_str(args[i]).map_err(|_| InstructionParseError::new(i, ParseErrorKind::BadRegister(args[i].to_string())))
+}
+
+fn parse_reg_or_imm(args: &[&str], i: usize) -> Result<RegOrImm, InstructionParseError> {
+    RegOrImm::from_str(args[i]).map_err(|_| InstructionParseError::new(i, ParseErrorKind::BadImmediate(args[i].to_string())))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -506,8 +574,37 @@ mod tests {
         )
     }
     #[test]
+    fn instruction_to_str() {
+        for instr in ["MOV R1 -3", "ADD R1 R2 R3", "TSTE R1 0", "LOAD R2 BP", "JUMP -4", "POP ZR", "RET"].iter() {
+            assert_eq!(&Instruction::from_str(instr).unwrap().to_str(), instr);
+        }
+    }
+    #[test]
     fn reg_to_str() {
         assert_eq!(Register::R1.to_str(), "R1");
         assert_eq!(Register::R2.to_str(), "R2");
     }
+    #[test]
+    fn from_str_errors() {
+        assert_eq!(
+            Instruction::from_str("MOVE R1 3").unwrap_err(),
+            InstructionParseError::new(0, ParseErrorKind::UnknownMnemonic("MOVE".to_string()))
+        );
+        assert_eq!(
+            Instruction::from_str("ADD R1 R2").unwrap_err(),
+            InstructionParseError::new(0, ParseErrorKind::WrongOperandCount { expected: 3, found: 2 })
+        );
+        assert_eq!(
+            Instruction::from_str("PUSH R1 R2").unwrap_err(),
+            InstructionParseError::new(2, ParseErrorKind::WrongOperandCount { expected: 1, found: 2 })
+        );
+        assert_eq!(
+            Instruction::from_str("MOV R9 3").unwrap_err(),
+            InstructionParseError::new(1, ParseErrorKind::BadRegister("R9".to_string()))
+        );
+        assert_eq!(
+            Instruction::from_str("TSTE R1 x").unwrap_err(),
+            InstructionParseError::new(2, ParseErrorKind::BadImmediate("x".to_string()))
+        );
+    }
 }
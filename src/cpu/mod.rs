@@ -2,13 +2,41 @@ pub mod instructions;
 
 use self::instructions::*;
 use std::collections::HashMap;
+use std::fmt;
 
+/// an error of the executed instruction, which stops the program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    InvalidAccess(u32), // read of an address that was never written
+    NotNumeric(u32), // read of an instruction as a number
+    NotExecutable(u32), // execution of a number
+    DivisionByZero,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::InvalidAccess(addr) => write!(f, "invalid memory access: {}", addr),
+            Fault::NotNumeric(addr) => write!(f, "the instruction at {} isn't a numeric value", addr),
+            Fault::NotExecutable(addr) => write!(f, "cannot execute data at {}", addr),
+            Fault::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Registers {
     values: HashMap<Register, i32>,
 }
 
+impl Default for Registers {
+    fn default() -> Self {
+        Registers::new()
+    }
+}
+
 impl Registers {
-    fn new() -> Registers {
+    pub fn new() -> Registers {
         let mut instance = Registers {
             values: HashMap::new(),
         };
@@ -40,39 +68,67 @@ impl Registers {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum MemEntry {
     Num(i32),
     Instruction(Instruction),
 }
 
+#[derive(Clone)]
 pub struct Memory {
     data: HashMap<u32, MemEntry>,
+    last_write: Option<u32>,
 }
 impl Memory {
     fn new() -> Memory {
         Memory {
             data: HashMap::new(),
+            last_write: None,
         }
     }
     pub fn get(&self, address: u32) -> &MemEntry {
-        self.data
-            .get(&address)
-            .expect(format!("Invalid memory access: {}", address).as_str())
+        self.try_get(address).unwrap_or_else(|fault| panic!("{}", fault))
+    }
+    pub fn try_get(&self, address: u32) -> Result<&MemEntry, Fault> {
+        self.data.get(&address).ok_or(Fault::InvalidAccess(address))
     }
     pub fn set(&mut self, address: u32, val: MemEntry) {
         self.data.insert(address, val);
+        self.last_write = Some(address);
+    }
+    /// the written addresses & their values, sorted by address
+    pub fn entries(&self) -> Vec<(u32, &MemEntry)> {
+        let mut entries: Vec<(u32, &MemEntry)> = self.data.iter().map(|(addr, entry)| (*addr, entry)).collect();
+        entries.sort_by_key(|(addr, _)| *addr);
+        entries
+    }
+    /// the address of the last write since the previous call
+    pub fn take_last_write(&mut self) -> Option<u32> {
+        self.last_write.take()
     }
     pub fn get_num(&self, address: u32) -> i32 {
-        match self.get(address) {
-            MemEntry::Num(x) => *x,
-            MemEntry::Instruction(_) => panic!("not numeric value"),
+        self.try_get_num(address).unwrap_or_else(|fault| panic!("{}", fault))
+    }
+    pub fn try_get_num(&self, address: u32) -> Result<i32, Fault> {
+        match self.try_get(address)? {
+            MemEntry::Num(x) => Ok(*x),
+            MemEntry::Instruction(_) => Err(Fault::NotNumeric(address)),
         }
     }
 }
 
+/// a change of the running function, for profiling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlowEvent {
+    Call(u32), // address of the callee
+    Return,
+}
+
+#[derive(Clone)]
 pub struct Cpu {
     pub mem: Memory,
     pub regs: Registers,
+    last_flow: Option<FlowEvent>,
 }
 
 impl Cpu {
@@ -80,14 +136,24 @@ impl Cpu {
         Cpu {
             mem: Memory::new(),
             regs: Registers::new(),
+            last_flow: None,
         }
     }
 
+    /// the CALL or RET executed since the previous call
+    pub fn take_flow_event(&mut self) -> Option<FlowEvent> {
+        self.last_flow.take()
+    }
+
     pub fn fetch(&self) -> Instruction {
-        if let MemEntry::Instruction(instr) = self.mem.get(self.regs.get(&Register::IR) as u32) {
-            return instr.clone();
+        self.try_fetch().unwrap_or_else(|fault| panic!("{}", fault))
+    }
+    pub fn try_fetch(&self) -> Result<Instruction, Fault> {
+        let ir = self.regs.get(&Register::IR) as u32;
+        match self.mem.try_get(ir)? {
+            MemEntry::Instruction(instr) => Ok(instr.clone()),
+            MemEntry::Num(_) => Err(Fault::NotExecutable(ir)),
         }
-        panic!("cannot execute data!");
     }
     fn execute_unary_arith(&mut self, op: &UnaryArithOp, arg: &Register) {
         let reg_val = self.regs.get(arg);
@@ -100,17 +166,21 @@ impl Cpu {
         dst: &Register,
         arg1: &Register,
         arg2: &RegOrImm,
-    ) {
+    ) -> Result<(), Fault> {
         let arg1_val = self.regs.get(arg1);
         let arg2_val = self.regs.get_reg_or_imm(arg2);
+        if matches!(op, BinArithOp::DIV | BinArithOp::MOD) && arg2_val == 0 {
+            return Err(Fault::DivisionByZero);
+        }
         let res = op.eval(arg1_val, arg2_val);
         self.regs.set(dst, res);
+        Ok(())
     }
-    fn execute_data(&mut self, op: &DataOp, dst: &Register, src: &RegOrImm) {
+    fn execute_data(&mut self, op: &DataOp, dst: &Register, src: &RegOrImm) -> Result<(), Fault> {
         let src_val = self.regs.get_reg_or_imm(src);
         match op {
             DataOp::LOAD => {
-                let mem_src_val = self.mem.get_num(src_val as u32);
+                let mem_src_val = self.mem.try_get_num(src_val as u32)?;
                 self.regs.set(dst, mem_src_val);
             }
             DataOp::STR => {
@@ -121,8 +191,9 @@ impl Cpu {
                 self.regs.set(dst, src_val);
             },
         }
+        Ok(())
     }
-    fn execute_stack(&mut self, op: &StackOp, dst: &Register) {
+    fn execute_stack(&mut self, op: &StackOp, dst: &Register) -> Result<(), Fault> {
         let sp = self.regs.get(&Register::SP);
         match op {
             StackOp::PUSH => {
@@ -131,10 +202,11 @@ impl Cpu {
                 self.regs.set(&Register::SP, sp - 1);
             }
             StackOp::POP => {
-                self.regs.set(dst, self.mem.get_num(sp as u32 + 1));
+                self.regs.set(dst, self.mem.try_get_num(sp as u32 + 1)?);
                 self.regs.set(&Register::SP, sp + 1);
             }
         }
+        Ok(())
     }
     fn execute_test(&mut self, op: &TestOp, arg1: &Register, arg2: &RegOrImm) {
         let arg1_val = self.regs.get(arg1);
@@ -158,76 +230,63 @@ impl Cpu {
             }
             let ir = self.regs.get(&Register::IR);
             self.regs.set(&Register::IR, ir + offset - 1);
+            if let FlowOp::CALL = op {
+                self.last_flow = Some(FlowEvent::Call((ir + offset) as u32));
+            }
         }
     }
-    fn execute_other(&mut self, op: &OtherOp) {
+    fn execute_other(&mut self, op: &OtherOp) -> Result<(), Fault> {
         match op {
             OtherOp::HALT => {}
             OtherOp::RET => {
                 let bp = self.regs.get(&Register::BP);
+                let ret_addr = self.mem.try_get_num(bp as u32 + 1)?;
+                let prev_bp = self.mem.try_get_num(bp as u32)?;
                 self.regs.set(&Register::SP, bp + 1);
-                let ret_addr = self.mem.get_num(bp as u32 + 1);
-                self.regs.set(&Register::BP, self.mem.get_num(bp as u32));
+                self.regs.set(&Register::BP, prev_bp);
                 self.regs.set(&Register::IR, ret_addr - 1); // IR will be increment at end of cycle
+                self.last_flow = Some(FlowEvent::Return);
             }
         }
+        Ok(())
     }
     /**
      * executes instruction
-     * returns whether CPU should keep running
+     * returns whether CPU should keep running, or the instruction's fault
      */
-    fn execute(&mut self, instr: &Instruction) -> bool {
+    fn execute(&mut self, instr: &Instruction) -> Result<bool, Fault> {
         match instr {
-            Instruction::UnaryArith { op, arg } => {
-                self.execute_unary_arith(op, arg);
-                return true;
-            }
+            Instruction::UnaryArith { op, arg } => self.execute_unary_arith(op, arg),
             Instruction::BinArith {
                 op,
                 dst,
                 arg1,
                 arg2,
-            } => {
-                self.execute_bin_arith(op, dst, arg1, arg2);
-                return true;
-            }
-            Instruction::Data { op, dst, src } => {
-                self.execute_data(op, dst, src);
-                return true;
-            }
-            Instruction::Stack { op, dst } => {
-                self.execute_stack(op, dst);
-                return true;
-            }
-            Instruction::Test { op, arg1, arg2 } => {
-                self.execute_test(op, arg1, arg2);
-                return true;
-            }
-            Instruction::Flow { op, offset } => {
-                self.execute_flow(op, *offset);
-                return true;
-            }
-            Instruction::Other { op } => {
-                self.execute_other(op);
-                return if let OtherOp::HALT = op { false } else { true };
-            }
+            } => self.execute_bin_arith(op, dst, arg1, arg2)?,
+            Instruction::Data { op, dst, src } => self.execute_data(op, dst, src)?,
+            Instruction::Stack { op, dst } => self.execute_stack(op, dst)?,
+            Instruction::Test { op, arg1, arg2 } => self.execute_test(op, arg1, arg2),
+            Instruction::Flow { op, offset } => self.execute_flow(op, *offset),
+            Instruction::Other { op } => self.execute_other(op)?,
         }
+        Ok(!matches!(instr, Instruction::Other { op: OtherOp::HALT }))
     }
 
-    pub fn step(&mut self) -> bool{
-        let instr = self.fetch();
-        let keep_running = self.execute(&instr);
+    /// on a fault the registers are left as they were before the instruction, IR points at it
+    pub fn step(&mut self) -> Result<bool, Fault> {
+        let instr = self.try_fetch()?;
+        let keep_running = self.execute(&instr)?;
         let ir = self.regs.get(&Register::IR);
         self.regs.set(&Register::IR, ir + 1);
 
-        keep_running
+        Ok(keep_running)
     }
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self) -> Result<(), Fault> {
         loop {
-            let keep_running = self.step();
+            let keep_running = self.step()?;
             if !keep_running {
-                break;
+                return Ok(());
             }
         }
     }
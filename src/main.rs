@@ -1,36 +1,174 @@
-#[macro_use] extern crate matches;
-mod cpu;
-mod operating_system;
+extern crate simple_vm;
 
-use crate::operating_system::compiler::Compiler;
-use crate::operating_system::OS;
+use simple_vm::operating_system::assembler::{assemble_object, format_errors};
+use simple_vm::operating_system::backtrace::format_backtrace;
+use simple_vm::operating_system::compiler::{CompileOptions, Compiler};
+use simple_vm::operating_system::core_dump::debug_core;
+use simple_vm::operating_system::dead_code::eliminate_dead_code;
+use simple_vm::operating_system::exec_file::*;
+use simple_vm::operating_system::linker::{link_with_map, select_archive_members, Archive, LinkMap};
+use simple_vm::operating_system::profiler::Profile;
+use simple_vm::operating_system::run::RunStatus;
+use simple_vm::operating_system::{OsConfig, OS};
 use std::env;
+use std::fs;
+use std::time::Duration;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3{
-        panic!("Usage: [run|debug] path_to_c_file/s")
-    }
-    let mut os = OS::new();
+fn compile_programs(os: &OS, paths: &[String]) -> Vec<String> {
     let mut programs = Vec::new();
-    for program_i in 2..args.len(){
-        println!("compiling: {}", args[program_i]);
-        let program = os.compile(&args[program_i]);
+    for path in paths.iter(){
+        println!("compiling: {}", path);
+        let program = os.compile(path);
         let lines: Vec<&str> = program.split("\n").collect();
         for (line_i, line) in lines.iter().enumerate(){
             println!("{}: {}", line_i, line);
         }
         programs.push(program);
     }
-    let programs = programs.iter().map(|s| s.as_str()).collect();
-    let mut res = -1;
-    if args[1] == "run"{
-        res = os.assemble_link_and_run(programs);
-    } else if args[1] == "debug"{
-        res = os.assemble_and_debug(programs);
-    }else{
-        panic!("invalid run mode")
+    programs
+}
+
+fn write_map(map: &LinkMap, path: &Option<String>) {
+    if let Some(path) = path {
+        fs::write(path, map.to_string()).unwrap_or_else(|e| panic!("failed to write {}: {}", path, e));
+        println!("wrote map: {}", path);
+    }
+}
+
+/// removes "name value" from the args & returns the value, what describes the expected value
+fn take_option(args: &mut Vec<String>, name: &str, what: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == name)?;
+    if i + 1 >= args.len() {
+        panic!("{} expects {}", name, what);
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Some(value)
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    // the arguments after -- are passed to the program's main
+    let program_args = match args.iter().position(|arg| arg == "--") {
+        Some(sep_i) => args.split_off(sep_i)[1..].to_vec(),
+        None => Vec::new(),
+    };
+    let mut options = CompileOptions::default();
+    // --annotate: interleave the C source lines as comments in the generated assembly
+    // --no-optimize: skip the peephole optimizer, keeps the generated assembly as is for debugging
+    args.retain(|arg| {
+        if arg == "--annotate" {
+            options.annotate_source = true;
+            return false;
+        }
+        if arg == "--no-optimize" {
+            options.optimize = false;
+            return false;
+        }
+        true
+    });
+    // --map path_to_map_file: write where the linker placed every symbol (build & link)
+    let map_path = take_option(&mut args, "--map", "a path to a map file");
+    // --fs-root path_to_dir: the host directory backing the files programs open (run, debug & exec)
+    let fs_root = take_option(&mut args, "--fs-root", "a path to a directory").map(|root| root.into());
+    // --core-dir path_to_dir: write the core file of a faulting program there, open it with the core command (run & exec)
+    let core_dir = take_option(&mut args, "--core-dir", "a path to a directory").map(|dir| dir.into());
+    let config = OsConfig { fs_root, core_dir, ..OsConfig::default() };
+    // --env NAME=value: adds a string to the program's environment, may be repeated (run, debug & exec)
+    let mut program_env = Vec::new();
+    while let Some(var) = take_option(&mut args, "--env", "a NAME=value string") {
+        program_env.push(var);
+    }
+    // --max-instructions n & --timeout seconds: stop the program when it exceeds them (run & exec)
+    let max_instructions = take_option(&mut args, "--max-instructions", "a number of instructions")
+        .map(|max| max.parse().unwrap_or_else(|_| panic!("invalid --max-instructions: {}", max)));
+    let timeout = take_option(&mut args, "--timeout", "a number of seconds")
+        .map(|secs| Duration::from_secs_f64(secs.parse().unwrap_or_else(|_| panic!("invalid --timeout: {}", secs))));
+    // --profile path_to_folded_file: print a profile of the program's functions, & write its stacks in the folded format of flame graph tools (run & exec)
+    let profile_path = take_option(&mut args, "--profile", "a path to a folded stacks file");
+    if args.len() < 3 || (["build", "compile", "ar", "link"].contains(&args[1].as_str()) && args.len() < 4){
+        panic!("Usage: [--annotate] [--no-optimize] [--map path_to_map_file] [--fs-root path_to_dir] [--env NAME=value] [--max-instructions n] [--timeout seconds] [--core-dir path_to_dir] [--profile path_to_folded_file] [run|debug] path_to_c_file/s | build path_to_executable path_to_c_file/s | exec path_to_executable [-- program_args]
+       core path_to_core_file | compile path_to_c_file path_to_object | ar path_to_archive path_to_object/s | link path_to_executable path_to_object/s_and_archive/s")
+    }
+    if args[1] == "core"{
+        let core = load_core(&args[2]).unwrap_or_else(|e| panic!("failed to load {}: {}", args[2], e));
+        debug_core(&core);
+        return;
+    }
+    if args[1] == "compile"{
+        let program = Compiler::compile_with_options(&args[2], &options);
+        let object = assemble_object(&program, &args[2]).unwrap_or_else(|errors| panic!("assembly failed:\n{}", format_errors(&errors)));
+        save_object(&object, &args[3]).unwrap_or_else(|e| panic!("failed to write {}: {}", args[3], e));
+        println!("wrote object: {}", args[3]);
+        return;
+    }
+    if args[1] == "ar"{
+        let members = args[3..].iter().map(|path| load_object(path).unwrap_or_else(|e| panic!("failed to load {}: {}", path, e))).collect();
+        save_archive(&Archive { members }, &args[2]).unwrap_or_else(|e| panic!("failed to write {}: {}", args[2], e));
+        println!("wrote archive: {}", args[2]);
+        return;
+    }
+    if args[1] == "link"{
+        let mut objects = Vec::new();
+        let mut archives = Vec::new();
+        for path in args[3..].iter(){
+            if path.ends_with(".a"){
+                archives.push(load_archive(path).unwrap_or_else(|e| panic!("failed to load {}: {}", path, e)));
+            } else {
+                objects.push(load_object(path).unwrap_or_else(|e| panic!("failed to load {}: {}", path, e)));
+            }
+        }
+        let (exec, map) = link_with_map(&eliminate_dead_code(&select_archive_members(&objects, &archives)))
+            .unwrap_or_else(|errors| panic!("linking failed:\n{}", format_errors(&errors)));
+        write_map(&map.with_layout(config.layout), &map_path);
+        save_executable(&exec, &args[2]).unwrap_or_else(|e| panic!("failed to write {}: {}", args[2], e));
+        println!("wrote executable: {}", args[2]);
+        return;
+    }
+    let mut os = OS::with_config(config);
+    os.compile_options = options;
+    // argv[0] is the executable or the main source file
+    os.args = vec![args[2].clone()];
+    os.args.extend(program_args);
+    os.env = program_env;
+    os.limits.max_instructions = max_instructions;
+    os.limits.timeout = timeout;
+    if profile_path.is_some() {
+        os.profile = Some(Profile::new());
+    }
+    let status;
+    if args[1] == "exec"{
+        let exec = load_executable(&args[2]).unwrap_or_else(|e| panic!("failed to load {}: {}", args[2], e));
+        status = os.run(&exec).status;
+    } else if args[1] == "build"{
+        let programs = compile_programs(&os, &args[3..]);
+        let programs = programs.iter().map(|s| s.as_str()).collect();
+        let (exec, map) = os.link_with_std_and_map(programs).unwrap_or_else(|errors| panic!("assembly failed:\n{}", format_errors(&errors)));
+        write_map(&map, &map_path);
+        save_executable(&exec, &args[2]).unwrap_or_else(|e| panic!("failed to write {}: {}", args[2], e));
+        println!("wrote executable: {}", args[2]);
+        return;
+    } else {
+        let programs = compile_programs(&os, &args[2..]);
+        let programs = programs.iter().map(|s| s.as_str()).collect();
+        if args[1] == "run"{
+            let exec = os.link_with_std(programs).unwrap_or_else(|errors| panic!("assembly failed:\n{}", format_errors(&errors)));
+            status = os.run(&exec).status;
+        } else if args[1] == "debug"{
+            status = os.assemble_and_debug(programs);
+        }else{
+            panic!("invalid run mode")
+        }
+    }
+    if let (Some(profile), Some(path)) = (&os.profile, &profile_path) {
+        println!("\n--------\n{}", profile.flat_profile());
+        fs::write(path, profile.folded_stacks()).unwrap_or_else(|e| panic!("failed to write {}: {}", path, e));
+        println!("wrote folded stacks: {}", path);
     }
     println!("\n--------");
-    println!("Return code:{}", res);
+    match status {
+        RunStatus::Exited(res) => println!("Return code:{}", res),
+        RunStatus::Fault(fault) => print!("Fault: {}\n{}", fault, format_backtrace(&fault.backtrace)),
+        RunStatus::LimitExceeded(e) => println!("Stopped: {}", e),
+    }
 }
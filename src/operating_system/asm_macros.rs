@@ -0,0 +1,237 @@
+use super::assembler::{strip_comment, AsmError, AsmErrorCause};
+use std::collections::HashMap;
+
+// macros may invoke other macros, this bounds the nesting so that recursive macros are reported instead of looping forever
+const MAX_MACRO_DEPTH: usize = 32;
+
+/// a line of a program after macro expansion
+/// line_i is the 0-based index of the source line the text came from,
+/// lines produced by expanding a macro refer to the line that invoked it
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceLine {
+    pub line_i: usize,
+    pub text: String,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+fn error_at(line_i: usize, text: &str, token_i: usize, cause: AsmErrorCause) -> AsmError {
+    AsmError {
+        program: 0,
+        line: line_i + 1,
+        column: super::assembler::token_column(text, token_i),
+        cause,
+    }
+}
+
+/// replaces every whitespace separated token of text that's a key of substitutions, keeping the whitespace as is
+/// the contents of .stringz & .string strings are left untouched
+fn substitute_tokens(text: &str, substitutions: &HashMap<String, String>) -> String {
+    let directive = text.split_whitespace().next();
+    let mut verbatim = false;
+    let mut res = String::new();
+    let mut rest = text;
+    let mut token_i = 0;
+    loop {
+        let ws_len = rest.len() - rest.trim_start().len();
+        res.push_str(&rest[..ws_len]);
+        rest = &rest[ws_len..];
+        if rest.is_empty() {
+            break;
+        }
+        let token_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let token = &rest[..token_len];
+        verbatim |= (directive == Some(".stringz") && token_i >= 2) || (directive == Some(".string") && token.starts_with('"'));
+        match substitutions.get(token) {
+            Some(value) if !verbatim => res.push_str(value),
+            _ => res.push_str(token),
+        }
+        token_i += 1;
+        rest = &rest[token_len..];
+    }
+    res
+}
+
+struct Expander {
+    constants: HashMap<String, String>,
+    macros: HashMap<String, Macro>,
+    lines: Vec<SourceLine>,
+    errors: Vec<AsmError>,
+}
+
+impl Expander {
+    fn expand_line(&mut self, line_i: usize, text: &str, depth: usize) {
+        let text = substitute_tokens(text, &self.constants);
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let mac = match tokens.first().and_then(|name| self.macros.get(*name)) {
+            Some(mac) => mac,
+            None => {
+                self.lines.push(SourceLine { line_i, text });
+                return;
+            }
+        };
+        if depth >= MAX_MACRO_DEPTH {
+            self.errors.push(error_at(line_i, &text, 0, AsmErrorCause::RecursiveMacro(tokens[0].to_string())));
+            return;
+        }
+        let args = &tokens[1..];
+        if args.len() != mac.params.len() {
+            let token = if args.len() > mac.params.len() { mac.params.len() + 1 } else { 0 };
+            let cause = AsmErrorCause::WrongOperandCount { expected: mac.params.len(), found: args.len() };
+            self.errors.push(error_at(line_i, &text, token, cause));
+            return;
+        }
+        let params: HashMap<String, String> = mac.params.iter().cloned()
+            .zip(args.iter().map(|arg| arg.to_string()))
+            .collect();
+        let body: Vec<String> = mac.body.iter().map(|line| substitute_tokens(line, &params)).collect();
+        for body_line in body.iter() {
+            self.expand_line(line_i, body_line, depth + 1);
+        }
+    }
+
+    fn define_constant(&mut self, line_i: usize, text: &str) {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        if tokens.len() != 3 {
+            let token = if tokens.len() > 3 { 3 } else { 0 };
+            let cause = AsmErrorCause::WrongOperandCount { expected: 2, found: tokens.len() - 1 };
+            self.errors.push(error_at(line_i, text, token, cause));
+            return;
+        }
+        if self.constants.contains_key(tokens[1]) || self.macros.contains_key(tokens[1]) {
+            self.errors.push(error_at(line_i, text, 1, AsmErrorCause::DuplicateSymbol(tokens[1].to_string())));
+            return;
+        }
+        // the value may itself be a previously defined constant
+        let value = self.constants.get(tokens[2]).map(|v| v.as_str()).unwrap_or(tokens[2]);
+        match value.parse::<i32>() {
+            Ok(value) => {
+                self.constants.insert(tokens[1].to_string(), value.to_string());
+            }
+            Err(_) => self.errors.push(error_at(line_i, text, 2, AsmErrorCause::BadOperand(tokens[2].to_string()))),
+        }
+    }
+
+    /// defines the macro that starts at lines[start], returns the index of the line after its .endm
+    fn define_macro(&mut self, lines: &[&str], start: usize) -> usize {
+        let text = lines[start];
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let end = (start + 1..lines.len()).find(|i| lines[*i].split_whitespace().next() == Some(".endm"));
+        if tokens.len() < 2 {
+            self.errors.push(error_at(start, text, 0, AsmErrorCause::WrongOperandCount { expected: 1, found: 0 }));
+            return end.map(|end| end + 1).unwrap_or(lines.len());
+        }
+        let name = tokens[1].to_string();
+        let end = match end {
+            Some(end) => end,
+            None => {
+                self.errors.push(error_at(start, text, 1, AsmErrorCause::UnterminatedMacro(name)));
+                return lines.len();
+            }
+        };
+        if self.macros.contains_key(&name) || self.constants.contains_key(&name) {
+            self.errors.push(error_at(start, text, 1, AsmErrorCause::DuplicateSymbol(name)));
+            return end + 1;
+        }
+        let mac = Macro {
+            params: tokens[2..].iter().map(|param| param.to_string()).collect(),
+            body: lines[start + 1..end].iter().map(|line| line.to_string()).collect(),
+        };
+        self.macros.insert(name, mac);
+        end + 1
+    }
+}
+
+/// expands the .equ constants & .macro definitions of a program
+/// a constant is substituted for every whitespace separated token that equals its name in the lines that follow its definition,
+/// a macro is invoked by a line that starts with its name followed by one argument per parameter,
+/// & is replaced by its body with every parameter token replaced by the corresponding argument
+/// comments are removed from the lines before anything else
+/// errors are reported with program index 0, the caller is in charge of setting the right index
+pub fn expand_macros(program: &str) -> (Vec<SourceLine>, Vec<AsmError>) {
+    let lines: Vec<&str> = program.split("\n").map(strip_comment).collect();
+    let mut expander = Expander {
+        constants: HashMap::new(),
+        macros: HashMap::new(),
+        lines: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut line_i = 0;
+    while line_i < lines.len() {
+        let text = lines[line_i];
+        match text.split_whitespace().next() {
+            Some(".equ") => expander.define_constant(line_i, text),
+            Some(".macro") => {
+                line_i = expander.define_macro(&lines, line_i);
+                continue;
+            }
+            Some(".endm") => expander.errors.push(error_at(line_i, text, 0, AsmErrorCause::InvalidDirective(".endm".to_string()))),
+            _ => expander.expand_line(line_i, text, 0),
+        }
+        line_i += 1;
+    }
+    (expander.lines, expander.errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn texts(lines: &[SourceLine]) -> Vec<String> {
+        lines.iter().map(|line| line.text.trim().to_string()).filter(|line| !line.is_empty()).collect()
+    }
+    #[test]
+    fn test_constants() {
+        let (lines, errors) = expand_macros("
+        .equ SIZE 3
+        .equ COUNT SIZE
+        .block buf SIZE
+        .stringz s SIZE
+        MOV R1 COUNT
+        ");
+        assert!(errors.is_empty());
+        assert_eq!(texts(&lines), vec![".block buf 3", ".stringz s SIZE", "MOV R1 3"]);
+        assert_eq!(lines.iter().find(|line| line.text.contains("MOV")).unwrap().line_i, 5);
+    }
+    #[test]
+    fn test_macros() {
+        let (lines, errors) = expand_macros("
+        .macro SAVE a b
+        PUSH a
+        PUSH b
+        .endm
+        .macro CALL_SAVED f
+        SAVE R1 R2
+        CALL f
+        .endm
+        CALL_SAVED F
+        ");
+        assert!(errors.is_empty());
+        assert_eq!(texts(&lines), vec!["PUSH R1", "PUSH R2", "CALL F"]);
+        assert!(lines.iter().filter(|line| !line.text.trim().is_empty()).all(|line| line.line_i == 9));
+    }
+    #[test]
+    fn test_macro_errors() {
+        let (_, errors) = expand_macros("
+        .macro REC
+        REC
+        .endm
+        .macro ONE a
+        PUSH a
+        .endm
+        REC
+        ONE R1 R2
+        .equ X y
+        .macro OPEN
+        ");
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[0].cause, AsmErrorCause::RecursiveMacro("REC".to_string()));
+        assert_eq!((errors[0].line, errors[0].column), (8, 9));
+        assert_eq!(errors[1].cause, AsmErrorCause::WrongOperandCount { expected: 1, found: 2 });
+        assert_eq!((errors[1].line, errors[1].column), (9, 16));
+        assert_eq!(errors[2].cause, AsmErrorCause::BadOperand("y".to_string()));
+        assert_eq!(errors[3].cause, AsmErrorCause::UnterminatedMacro("OPEN".to_string()));
+    }
+}
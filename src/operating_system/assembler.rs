@@ -1,12 +1,125 @@
 use crate::cpu::instructions::*;
-use super::layout::DATA_INIT_ADDRESS;
+use super::asm_macros::{expand_macros, SourceLine};
+use super::dead_code::eliminate_dead_code;
+use super::linker::{link_with_map, LinkMap};
 use std::collections::HashMap;
-use std::collections::HashSet;
-use std::collections::hash_set::Intersection;
 use std::str::FromStr;
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum AsmErrorCause {
+    UnknownMnemonic(String),
+    WrongOperandCount { expected: usize, found: usize },
+    BadRegister(String),
+    BadOperand(String),
+    UndefinedLabel(String),
+    InvalidDirective(String),
+    DuplicateSymbol(String),
+    MultipleDefinition { label: String, first: String, second: String }, // a global symbol defined by two objects, named by the objects
+    UnterminatedMacro(String),
+    RecursiveMacro(String),
+}
+
+impl AsmErrorCause {
+    fn from(kind: ParseErrorKind) -> AsmErrorCause {
+        match kind {
+            ParseErrorKind::Empty => AsmErrorCause::UnknownMnemonic("".to_string()),
+            ParseErrorKind::UnknownMnemonic(m) => AsmErrorCause::UnknownMnemonic(m),
+            ParseErrorKind::WrongOperandCount { expected, found } => AsmErrorCause::WrongOperandCount { expected, found },
+            ParseErrorKind::BadRegister(r) => AsmErrorCause::BadRegister(r),
+            ParseErrorKind::BadImmediate(v) => AsmErrorCause::BadOperand(v),
+        }
+    }
+}
+
+impl std::fmt::Display for AsmErrorCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AsmErrorCause::UnknownMnemonic(m) => write!(f, "unknown mnemonic '{}'", m),
+            AsmErrorCause::WrongOperandCount { expected, found } => {
+                write!(f, "wrong operand count: expected {}, found {}", expected, found)
+            }
+            AsmErrorCause::BadRegister(r) => write!(f, "bad register '{}'", r),
+            AsmErrorCause::BadOperand(v) => write!(f, "bad operand '{}'", v),
+            AsmErrorCause::UndefinedLabel(l) => write!(f, "undefined label '{}'", l),
+            AsmErrorCause::InvalidDirective(d) => write!(f, "invalid data directive '{}'", d),
+            AsmErrorCause::DuplicateSymbol(l) => write!(f, "duplicate symbol '{}'", l),
+            AsmErrorCause::MultipleDefinition { label, first, second } => {
+                write!(f, "symbol '{}' is defined in both '{}' and '{}'", label, first, second)
+            }
+            AsmErrorCause::UnterminatedMacro(m) => write!(f, "macro '{}' has no matching .endm", m),
+            AsmErrorCause::RecursiveMacro(m) => write!(f, "macro '{}' expands recursively", m),
+        }
+    }
+}
+
+/// a single assembly error
+/// program is the index of the program in the list passed to assemble_and_link,
+/// line & column are 1-based & relative to that program's source
+#[derive(Debug, PartialEq, Clone)]
+pub struct AsmError {
+    pub program: usize,
+    pub line: usize,
+    pub column: usize,
+    pub cause: AsmErrorCause,
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "program {}, line {}, column {}: {}", self.program, self.line, self.column, self.cause)
+    }
+}
+
+pub fn format_errors(errors: &[AsmError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("\n")
+}
+
+/// returns the 1-based column at which the token_i'th whitespace separated token of line starts
+pub fn token_column(line: &str, token_i: usize) -> usize {
+    let mut tokens_seen = 0;
+    let mut in_token = false;
+    for (col, c) in line.char_indices() {
+        if c.is_whitespace() {
+            in_token = false;
+        } else if !in_token {
+            if tokens_seen == token_i {
+                return col + 1;
+            }
+            tokens_seen += 1;
+            in_token = true;
+        }
+    }
+    line.len() + 1
+}
+
+fn line_error(program: usize, line_i: usize, line: &str, token_i: usize, cause: AsmErrorCause) -> AsmError {
+    AsmError {
+        program,
+        line: line_i + 1,
+        column: token_column(line, token_i),
+        cause,
+    }
+}
+
+/// returns line without its comment, comments start at a ';' or '#' that's not inside a double quoted string
+pub fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' | '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// line classification expects lines without comments, see strip_comment
 fn is_label(line: &str) -> bool {
-    line.contains(":")
+    let line = line.trim();
+    line.ends_with(':') && !line.contains(char::is_whitespace)
 }
 
 fn get_label_from_line(line: &str) -> Option<String> {
@@ -20,170 +133,731 @@ fn is_instruction(line: &str) -> bool {
     !is_label(line) && !is_data(line) && line.trim() != ""
 }
 
-fn maybe_parse_instruction(
-    line: &str,
-    symbol_table: &HashMap<String, u32>,
-    data_table: &HashMap<String, u32>,
-    cur_rel_address: u32,
-) -> Option<Instruction> {
-    if is_instruction(line) {
-        let args: Vec<&str> = line.split_whitespace().collect();
-        // if line is flow instruction
-        if let Result::Ok(_) = FlowOp::from_str(args[0]) {
-            // replace label string with numeric offset
-            let label = String::from(args[1]);
-            assert!(symbol_table.contains_key(&label), format!("label:{} does not exist in symbol table", label));
-            let offset = (*symbol_table.get(&label).unwrap() as i32) - (cur_rel_address as i32);
-            return Some(Instruction::from_str(&format!("{} {}", args[0], offset)).unwrap());
-        }
-        if let Result::Ok(lea) = DataOp::from_str(args[0]){
-            if matches!(lea, DataOp::LEA) {
-                let dst = String::from(args[1]);
-                let label = String::from(args[2]);
-                assert!(data_table.contains_key(&label), format!("label:{} does not exist in data table", label));
-                let label_addr = data_table.get(&label).unwrap() + DATA_INIT_ADDRESS;
-                return Some(Instruction::from_str(&format!("LEA {} {}", dst, label_addr)).unwrap());
+/// 1-based location in the source of a program
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceLoc {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLoc {
+    fn of_token(line_i: usize, line: &str, token_i: usize) -> SourceLoc {
+        SourceLoc {
+            line: line_i + 1,
+            column: token_column(line, token_i),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Binding {
+    Global, // visible to all objects
+    Local,  // labels starting with .L & symbols declared .local, only visible to the object that defines them
+    Weak,   // visible to all objects, a global definition in another object takes precedence
+}
+
+/// labels starting with .L are local to the object that defines them, other labels are global unless declared otherwise
+pub fn binding_of(label: &str) -> Binding {
+    if is_temporary_label(label) { Binding::Local } else { Binding::Global }
+}
+
+/// .L labels are the assembler's own (jump targets, string literals, ...), other labels start a function or a data object
+pub fn is_temporary_label(label: &str) -> bool {
+    label.starts_with(".L")
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub addr: u32, // relative to the start of the object's code or data
+    pub size: u32, // code: up to the next non .L label, data: the words of its directive & the unlabeled ones that follow
+    pub loc: SourceLoc,
+    pub binding: Binding,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RelocationKind {
+    CodeOffset, // flow instructions, patched with (target address - instruction address), labels are code labels
+    Immediate,  // immediate operands, patched with the value of the expression, labels are absolute data or code addresses
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Term {
+    Const(i32),
+    Label(String),
+    SizeOf(String),
+}
+
+/// an operand expression, e.g label+4, label-other_label or sizeof(label)
+/// the value is the sum of the terms, each multiplied by its sign (1 or -1)
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expr {
+    pub terms: Vec<(i32, Term)>,
+}
+
+fn is_label_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Option<Expr> {
+        let mut terms = Vec::new();
+        let mut rest = s;
+        let mut sign = 1;
+        if let Some(r) = rest.strip_prefix('-') {
+            sign = -1;
+            rest = r;
+        }
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let term = &rest[..end];
+            let term = if let Ok(val) = term.parse() {
+                Term::Const(val)
+            } else if let Some(label) = term.strip_prefix("sizeof(").and_then(|t| t.strip_suffix(')')) {
+                if !is_label_name(label) {
+                    return None;
+                }
+                Term::SizeOf(label.to_string())
+            } else if is_label_name(term) {
+                Term::Label(term.to_string())
+            } else {
+                return None;
+            };
+            terms.push((sign, term));
+            if end == rest.len() {
+                break;
             }
+            sign = if rest[end..].starts_with('-') { -1 } else { 1 };
+            rest = &rest[end + 1..];
         }
-        if let Ok(instr) = Instruction::from_str(line) {
-            return Some(instr);
-        } 
+        Some(Expr { terms })
     }
-    None
+
+    pub fn to_str(&self) -> String {
+        let mut res = String::new();
+        for (term_i, (sign, term)) in self.terms.iter().enumerate() {
+            if *sign < 0 {
+                res.push('-');
+            } else if term_i > 0 {
+                res.push('+');
+            }
+            match term {
+                Term::Const(val) => res.push_str(&val.to_string()),
+                Term::Label(label) => res.push_str(label),
+                Term::SizeOf(label) => res.push_str(&format!("sizeof({})", label)),
+            }
+        }
+        res
+    }
+
+    /// labels referenced by the expression
+    pub fn labels(&self) -> Vec<&String> {
+        self.terms.iter().filter_map(|(_, term)| match term {
+            Term::Label(label) | Term::SizeOf(label) => Some(label),
+            Term::Const(_) => None,
+        }).collect()
+    }
+}
+
+/// a reference from an instruction to labels, resolved by the linker
+#[derive(Debug, PartialEq, Clone)]
+pub struct Relocation {
+    pub offset: u32, // index of the instruction or data word to patch, relative to the object's code or data
+    pub segment: Segment, // segment of the patched word
+    pub kind: RelocationKind,
+    pub expr: Expr,
+    pub loc: SourceLoc,
+}
+
+/// the result of assembling a single program (translation unit)
+/// all labels are left unresolved & are described by relocation entries
+#[derive(Debug, Clone)]
+pub struct ObjectFile {
+    pub name: String,
+    pub code: Vec<Instruction>,
+    pub data: Vec<i32>,
+    pub data_align: u32, // the absolute address of the object's data must be a multiple of this
+    pub code_symbols: HashMap<String, Symbol>,
+    pub data_symbols: HashMap<String, Symbol>,
+    pub relocations: Vec<Relocation>,
+    pub line_addrs: Vec<u32>, // relative code address of each source line, for setting breakpoints in debugger
+}
+
+impl ObjectFile {
+    /// symbols that are referenced but not defined in this object
+    pub fn undefined_symbols(&self) -> Vec<String> {
+        let mut undefined: Vec<String> = self.relocations.iter()
+            .flat_map(|reloc| reloc.expr.labels().into_iter().filter(move |label| !self.defines(&reloc.kind, label)))
+            .cloned()
+            .collect();
+        undefined.sort();
+        undefined.dedup();
+        undefined
+    }
+
+    /// the symbol a label refers to in a relocation of the given kind, data labels take precedence in immediates
+    pub fn symbol(&self, kind: &RelocationKind, symbol: &str) -> Option<&Symbol> {
+        match kind {
+            RelocationKind::CodeOffset => self.code_symbols.get(symbol),
+            RelocationKind::Immediate => self.data_symbols.get(symbol).or_else(|| self.code_symbols.get(symbol)),
+        }
+    }
+
+    pub fn defines(&self, kind: &RelocationKind, symbol: &str) -> bool {
+        self.symbol(kind, symbol).is_some()
+    }
+
+    /// whether the object defines a symbol that other objects can reference
+    pub fn exports(&self, kind: &RelocationKind, symbol: &str) -> bool {
+        self.symbol(kind, symbol).is_some_and(|symbol| symbol.binding != Binding::Local)
+    }
+}
+
+/// label referenced by an instruction: relocation kind, label & index of the label's token in the line
+type LabelRef = (RelocationKind, Expr, usize);
+
+/// parses a single instruction line
+/// the operand of flow instructions & immediate operands that reference labels are left as 0 and a relocation is returned for them
+/// errors hold the index of the offending token in the line
+fn parse_instruction(line: &str) -> Result<(Instruction, Option<LabelRef>), (usize, AsmErrorCause)> {
+    let mut args: Vec<&str> = line.split_whitespace().collect();
+    // if line is flow instruction
+    if FlowOp::from_str(args[0]).is_ok() {
+        if args.len() != 2 {
+            return Err(operand_count_error(&args, 1));
+        }
+        let expr = Expr::parse(args[1]).ok_or_else(|| (1, AsmErrorCause::BadOperand(args[1].to_string())))?;
+        let instr = Instruction::from_str(&format!("{} 0", args[0])).unwrap();
+        return Ok((instr, Some((RelocationKind::CodeOffset, expr, 1))));
+    }
+    match Instruction::from_str(line) {
+        Ok(instr) => Ok((instr, None)),
+        Err(InstructionParseError { token, kind: ParseErrorKind::BadImmediate(operand) }) => {
+            // an immediate operand that references labels
+            let expr = Expr::parse(&operand).ok_or_else(|| (token, AsmErrorCause::BadOperand(operand.clone())))?;
+            args[token] = "0";
+            let instr = Instruction::from_str(&args.join(" ")).map_err(|e| (e.token, AsmErrorCause::from(e.kind)))?;
+            Ok((instr, Some((RelocationKind::Immediate, expr, token))))
+        }
+        Err(e) => Err((e.token, AsmErrorCause::from(e.kind))),
+    }
+}
+
+fn operand_count_error(args: &[&str], expected: usize) -> (usize, AsmErrorCause) {
+    let found = args.len() - 1;
+    let token = if found > expected { expected + 1 } else { 0 };
+    (token, AsmErrorCause::WrongOperandCount { expected, found })
+}
+
+
+fn is_numeric_label(label: &str) -> bool {
+    !label.is_empty() && label.chars().all(|c| c.is_ascii_digit())
+}
+
+/// gives the local labels of a program's code unique names, in place
+/// a .L label is scoped to the non .L label that precedes it & is renamed to .L<name>$<scope>,
+/// a numeric label N may be defined many times, its k'th definition is renamed to .LN$k,
+/// a reference to Nf is to the next definition of N & Nb to the previous one
+/// only the operands of flow instructions refer to code labels, .L labels in immediate operands are data labels,
+/// which are scoped to the whole program & keep their names
+pub fn resolve_local_labels(lines: &mut [SourceLine]) -> Vec<AsmError> {
+    let mut errors = Vec::new();
+    let mut num_defs: HashMap<String, usize> = HashMap::new();
+    for line in lines.iter() {
+        if let Some(label) = get_label_from_line(&line.text) {
+            if is_numeric_label(&label) {
+                *num_defs.entry(label).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut scope = String::new();
+    let mut defs_seen: HashMap<String, usize> = HashMap::new();
+    for line in lines.iter_mut() {
+        let line_i = line.line_i;
+        if let Some(label) = get_label_from_line(&line.text) {
+            let new_label = if is_numeric_label(&label) {
+                let seen = defs_seen.entry(label.clone()).or_insert(0);
+                *seen += 1;
+                format!(".L{}${}", label, *seen - 1)
+            } else if label.starts_with(".L") {
+                format!("{}${}", label, scope)
+            } else {
+                scope = label;
+                continue;
+            };
+            let indent = line.text.len() - line.text.trim_start().len();
+            line.text = format!("{}{}:", &line.text[..indent], new_label);
+            continue;
+        }
+        if !is_instruction(&line.text) {
+            continue;
+        }
+        let args: Vec<&str> = line.text.split_whitespace().collect();
+        if args.len() != 2 || FlowOp::from_str(args[0]).is_err() {
+            continue;
+        }
+        let target = args[1];
+        let mut expr = match Expr::parse(target) {
+            Some(expr) => expr,
+            None => continue,
+        };
+        for (_, term) in expr.terms.iter_mut() {
+            let label = match term {
+                Term::Label(label) | Term::SizeOf(label) => label,
+                Term::Const(_) => continue,
+            };
+            let (num, direction) = label.split_at(label.len() - 1);
+            if label.starts_with(".L") {
+                *label = format!("{}${}", label, scope);
+            } else if is_numeric_label(num) && (direction == "f" || direction == "b") {
+                let seen = *defs_seen.get(num).unwrap_or(&0);
+                let def_i = if direction == "b" { seen.checked_sub(1) } else { Some(seen) };
+                match def_i.filter(|def_i| def_i < num_defs.get(num).unwrap_or(&0)) {
+                    Some(def_i) => *label = format!(".L{}${}", num, def_i),
+                    None => errors.push(line_error(0, line_i, &line.text, 1, AsmErrorCause::UndefinedLabel(label.clone()))),
+                }
+            }
+        }
+        let new_target = expr.to_str();
+        let start = token_column(&line.text, 1) - 1;
+        line.text = format!("{}{}{}", &line.text[..start], new_target, &line.text[start + target.len()..]);
+    }
+    errors
 }
 
+/// a .global, .local or .weak directive: the binding it gives & its line
+type VisibilityDirective = (Binding, SourceLine);
+
+/// removes the .global, .local & .weak directives from the lines & returns them
+/// they may name symbols defined anywhere in the program, so they're applied once the symbol tables are built
+fn take_visibility_directives(lines: &mut [SourceLine]) -> Vec<VisibilityDirective> {
+    let mut directives = Vec::new();
+    for line in lines.iter_mut() {
+        let binding = match line.text.split_whitespace().next() {
+            Some(".global") => Binding::Global,
+            Some(".local") => Binding::Local,
+            Some(".weak") => Binding::Weak,
+            _ => continue,
+        };
+        directives.push((binding, line.clone()));
+        line.text = String::new();
+    }
+    directives
+}
 
-/// returns program's symbol table & # of instructions
-pub fn gen_symbol_table(program: &str, start_addr: u32) -> (HashMap<String, u32>, u32){
+/// gives the symbols named by each visibility directive its binding, a directive names one or more code or data symbols
+/// .L labels are always local, naming one is an error
+fn apply_visibility(directives: &[VisibilityDirective], code_symbols: &mut HashMap<String, Symbol>, data_symbols: &mut HashMap<String, Symbol>) -> Vec<AsmError> {
+    let mut errors = Vec::new();
+    for (binding, SourceLine { line_i, text }) in directives.iter() {
+        let names: Vec<&str> = text.split_whitespace().skip(1).collect();
+        if names.is_empty() {
+            errors.push(line_error(0, *line_i, text, 0, AsmErrorCause::WrongOperandCount { expected: 1, found: 0 }));
+        }
+        for (name_i, name) in names.iter().enumerate() {
+            if is_temporary_label(name) {
+                errors.push(line_error(0, *line_i, text, name_i + 1, AsmErrorCause::BadOperand(name.to_string())));
+                continue;
+            }
+            let mut defined = false;
+            for symbols in [&mut *code_symbols, &mut *data_symbols] {
+                if let Some(symbol) = symbols.get_mut(*name) {
+                    symbol.binding = binding.clone();
+                    defined = true;
+                }
+            }
+            if !defined {
+                errors.push(line_error(0, *line_i, text, name_i + 1, AsmErrorCause::UndefinedLabel(name.to_string())));
+            }
+        }
+    }
+    errors
+}
+
+/// returns program's symbol table & # of instructions, given its lines after macro expansion
+/// labels that are defined more than once are returned as errors
+pub fn gen_symbol_table(lines: &[SourceLine]) -> (HashMap<String, Symbol>, u32, Vec<AsmError>){
     let mut symbol_table = HashMap::new();
-    let mut cur_address = start_addr;
+    let mut errors = Vec::new();
+    let mut cur_address = 0;
 
-    let lines: Vec<&str> = program.split("\n").collect();
-    for (line_i, line) in lines.iter().enumerate() {
+    for SourceLine { line_i, text: line } in lines.iter() {
+        let line_i = *line_i;
         if let Some(label) = get_label_from_line(line) {
-            symbol_table.insert(label, cur_address);
+            if symbol_table.contains_key(&label) {
+                errors.push(line_error(0, line_i, line, 0, AsmErrorCause::DuplicateSymbol(label)));
+                continue;
+            }
+            let binding = binding_of(&label);
+            symbol_table.insert(label, Symbol { addr: cur_address, size: 0, loc: SourceLoc::of_token(line_i, line, 0), binding });
         } else if is_instruction(line) {
             cur_address += 1;
         }
     }
+    // a label spans up to the next non .L label
+    let mut func_addrs: Vec<u32> = symbol_table.iter().filter(|(label, _)| !is_temporary_label(label)).map(|(_, symbol)| symbol.addr).collect();
+    func_addrs.sort();
+    for symbol in symbol_table.values_mut() {
+        let end = func_addrs.iter().find(|addr| **addr > symbol.addr).cloned().unwrap_or(cur_address);
+        symbol.size = end - symbol.addr;
+    }
 
-    (symbol_table, cur_address - start_addr)
+    (symbol_table, cur_address, errors)
 }
 
 fn is_data(line: &str) -> bool{
-    line.trim().starts_with(".")
+    !is_label(line) && line.trim().starts_with(".")
+}
+
+/// program's data, data table, the alignment its data must be placed at & the relocations of its data words
+pub type DataSection = (Vec<i32>, HashMap<String, Symbol>, u32, Vec<Relocation>);
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn parse_escape(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<i32> {
+    let c = chars.next()?;
+    let val = match c {
+        'n' => 10,
+        't' => 9,
+        'r' => 13,
+        'a' => 7,
+        'b' => 8,
+        'f' => 12,
+        'v' => 11,
+        '\\' | '\'' | '"' | '?' => c as i32,
+        '0'..='7' => { // up to 3 octal digits
+            let mut val = c.to_digit(8)?;
+            for _ in 0..2 {
+                match chars.peek().and_then(|d| d.to_digit(8)) {
+                    Some(d) => {
+                        val = val * 8 + d;
+                        chars.next();
+                    }
+                    None => break,
+                }
+            }
+            val as i32
+        }
+        'x' => {
+            let mut val = None;
+            while let Some(d) = chars.peek().and_then(|d| d.to_digit(16)) {
+                val = Some(val.unwrap_or(0) * 16 + d);
+                chars.next();
+            }
+            val? as i32
+        }
+        _ => return None,
+    };
+    Some(val)
+}
+
+/// parses a double quoted string with C escape sequences, returns its characters followed by a terminating 0
+fn parse_string_literal(literal: &str) -> Option<Vec<i32>> {
+    let mut chars = literal.strip_prefix('"')?.chars().peekable();
+    let mut vals = Vec::new();
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => vals.push(parse_escape(&mut chars)?),
+            c => vals.push(c as i32),
+        }
+    }
+    if chars.next().is_some() { // the closing quote must end the operand
+        return None;
+    }
+    vals.push(0);
+    Some(vals)
+}
+
+/// word of a .word directive that references labels: index of the word in the directive, its expression & the index of its token
+type WordRef = (usize, Expr, usize);
+
+/// the words of a .word directive, words that reference labels are left as 0 and returned as references
+fn parse_words(parts: &[&str], first: usize) -> Result<(Vec<i32>, Vec<WordRef>), (usize, AsmErrorCause)> {
+    if parts.len() <= first {
+        return Err((0, AsmErrorCause::WrongOperandCount { expected: first, found: parts.len() - 1 }));
+    }
+    let mut vals = Vec::new();
+    let mut refs = Vec::new();
+    for (token_i, part) in parts.iter().enumerate().skip(first) {
+        if let Ok(val) = part.parse() {
+            vals.push(val);
+            continue;
+        }
+        let expr = Expr::parse(part).ok_or_else(|| (token_i, AsmErrorCause::BadOperand(part.to_string())))?;
+        refs.push((vals.len(), expr, token_i));
+        vals.push(0);
+    }
+    Ok((vals, refs))
+}
+
+/// returns the values of a single data directive, given the index of its first operand
+fn parse_data_directive(line: &str, parts: &[&str], first: usize, cur_len: usize) -> Result<Vec<i32>, (usize, AsmErrorCause)> {
+    match parts[0]{
+        ".stringz" => { // zero terminated string, made of the rest of the line's words
+            if parts.len() < 2 {
+                return Err((0, AsmErrorCause::WrongOperandCount { expected: 2, found: parts.len() - 1 }));
+            }
+            let mut vals: Vec<i32> = parts[2..].join(" ").chars().map(|c| c as i32).collect();
+            vals.push(0);
+            Ok(vals)
+        },
+        ".string" => { // zero terminated, double quoted string with C escapes
+            let literal = line[token_column(line, first) - 1..].trim_end();
+            if literal.is_empty() {
+                return Err((0, AsmErrorCause::WrongOperandCount { expected: first, found: parts.len() - 1 }));
+            }
+            parse_string_literal(literal).ok_or_else(|| (first, AsmErrorCause::BadOperand(literal.to_string())))
+        },
+        ".block" => { // allocate a block of data
+            if parts.len() != 3 {
+                return Err(operand_count_error(parts, 2));
+            }
+            let block_size : u32 = parts[2].parse().map_err(|_| (2, AsmErrorCause::BadOperand(parts[2].to_string())))?;
+            Ok(vec![0; block_size as usize])
+        }
+        ".fill" => { // count copies of a value
+            if parts.len() != first + 2 {
+                return Err(operand_count_error(parts, first + 1));
+            }
+            let count: u32 = parts[first].parse().map_err(|_| (first, AsmErrorCause::BadOperand(parts[first].to_string())))?;
+            let val: i32 = parts[first + 1].parse().map_err(|_| (first + 1, AsmErrorCause::BadOperand(parts[first + 1].to_string())))?;
+            Ok(vec![val; count as usize])
+        }
+        ".align" => { // pad with zeros up to a multiple of n
+            if parts.len() != 2 {
+                return Err(operand_count_error(parts, 1));
+            }
+            let n: u32 = match parts[1].parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err((1, AsmErrorCause::BadOperand(parts[1].to_string()))),
+            };
+            Ok(vec![0; (n as usize - cur_len % n as usize) % n as usize])
+        }
+        _ => Err((0, AsmErrorCause::InvalidDirective(parts[0].to_string()))),
+    }
 }
 
-pub fn extract_data(program: &str, cur_data_size: u32) -> (Vec<i32>, HashMap<String, u32>){
+/// returns program's data & data table, given its lines after macro expansion
+/// .stringz & .block are always labeled, the label of .string, .word & .fill is optional,
+/// so the first word of an unlabeled .word or .fill must be a number
+/// .align aligns the next data relative to the program's data, the linker places the data at a multiple of all the alignments
+/// the words of .word may be label expressions, e.g .word table 1 other_table+2, which the linker resolves
+/// errors are reported with program index 0, the caller is in charge of setting the right index
+pub fn extract_data(lines: &[SourceLine]) -> Result<DataSection, Vec<AsmError>>{
     let mut data = Vec::new();
     let mut data_table = HashMap::new();
-    let lines: Vec<&str> = program.split("\n").collect();
-    for line in lines.iter() {
-        if is_data(line){
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            match parts[0]{
-                ".stringz" => { // zero terminated string
-                    let string_label = &parts[1];
-                    let string_parts = &parts[2..];
-                    let string = &string_parts.join(" ");
-                    data_table.insert(string_label.to_string(), cur_data_size + data.len() as u32);
-                    for val in string.chars() {
-                        data.push(val as i32);
-                    }
-                    data.push(0);
-                },
-                ".block" => { // allocate a block of data
-                    let block_label = &parts[1];
-                    let block_size = &parts[2];
-                    let block_size : u32 = block_size.parse().unwrap();
-                    data_table.insert(block_label.to_string(), cur_data_size + data.len() as u32);
-                    for _ in 0..block_size{
-                        data.push(0);
-                    }
+    let mut data_align = 1;
+    let mut relocations = Vec::new();
+    let mut errors = Vec::new();
+    let mut cur_label: Option<String> = None; // label that unlabeled data is added to
+    for SourceLine { line_i, text: line } in lines.iter() {
+        let line_i = *line_i;
+        if !is_data(line){
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        // a label of .word & .fill must be followed by the values
+        let is_labeled_values = (parts[0] == ".word" || parts[0] == ".fill") && parts.len() >= 2 && parts[1].parse::<i32>().is_err();
+        if is_labeled_values && !(is_label_name(parts[1]) && parts.len() >= 3) {
+            errors.push(line_error(0, line_i, line, 1, AsmErrorCause::BadOperand(parts[1].to_string())));
+            continue;
+        }
+        let has_label = match parts[0] {
+            ".stringz" | ".block" => parts.len() >= 2,
+            ".word" | ".fill" => is_labeled_values,
+            ".string" => parts.len() >= 2 && !parts[1].starts_with('"'),
+            _ => false,
+        };
+        if has_label && data_table.contains_key(parts[1]) {
+            errors.push(line_error(0, line_i, line, 1, AsmErrorCause::DuplicateSymbol(parts[1].to_string())));
+            continue;
+        }
+        let first = if has_label || parts[0] == ".stringz" || parts[0] == ".block" { 2 } else { 1 };
+        let parsed = if parts[0] == ".word" {
+            parse_words(&parts, first)
+        } else {
+            parse_data_directive(line, &parts, first, data.len()).map(|vals| (vals, Vec::new()))
+        };
+        match parsed {
+            Ok((vals, refs)) => {
+                if has_label {
+                    let symbol = Symbol { addr: data.len() as u32, size: 0, loc: SourceLoc::of_token(line_i, line, 1), binding: binding_of(parts[1]) };
+                    data_table.insert(parts[1].to_string(), symbol);
+                    cur_label = Some(parts[1].to_string());
+                }
+                if parts[0] == ".align" {
+                    let n: u32 = parts[1].parse().unwrap();
+                    data_align = data_align / gcd(data_align, n) * n;
+                    cur_label = None;
+                } else if let Some(symbol) = cur_label.as_ref().and_then(|label| data_table.get_mut(label)) {
+                    symbol.size += vals.len() as u32;
+                }
+                for (word_i, expr, token_i) in refs {
+                    relocations.push(Relocation {
+                        offset: (data.len() + word_i) as u32,
+                        segment: Segment::Data,
+                        kind: RelocationKind::Immediate,
+                        expr,
+                        loc: SourceLoc::of_token(line_i, line, token_i),
+                    });
+                }
+                data.extend(vals);
+            }
+            Err((token_i, cause)) => errors.push(line_error(0, line_i, line, token_i, cause)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok((data, data_table, data_align, relocations))
+}
 
+/// assembles a single program into an object file
+/// invalid instructions are replaced by HALT so that the addresses of the rest of the program stay correct,
+/// which lets the linker report errors of its own on top of the returned assembly errors
+fn assemble_object_with_errors(program: &str, name: &str) -> (ObjectFile, Vec<AsmError>) {
+    let (mut lines, mut errors) = expand_macros(program);
+    errors.extend(resolve_local_labels(&mut lines));
+    let visibility = take_visibility_directives(&mut lines);
+    let (mut code_symbols, _, symbol_errors) = gen_symbol_table(&lines);
+    errors.extend(symbol_errors);
+    let (data, mut data_symbols, data_align, mut relocations) = match extract_data(&lines) {
+        Ok(res) => res,
+        Err(data_errors) => {
+            errors.extend(data_errors);
+            (Vec::new(), HashMap::new(), 1, Vec::new())
+        }
+    };
+    errors.extend(apply_visibility(&visibility, &mut code_symbols, &mut data_symbols));
+    let mut code = Vec::new();
+    let mut line_addrs = Vec::new();
+    for SourceLine { line_i, text: line } in lines.iter() {
+        let line_i = *line_i;
+        // a source line's address is that of the first instruction expanded from it or from the lines that follow it
+        while line_addrs.len() <= line_i {
+            line_addrs.push(code.len() as u32);
+        }
+        if !is_instruction(line) {
+            continue;
+        }
+        match parse_instruction(line) {
+            Ok((instr, reloc)) => {
+                if let Some((kind, expr, token_i)) = reloc {
+                    relocations.push(Relocation {
+                        offset: code.len() as u32,
+                        segment: Segment::Code,
+                        kind,
+                        expr,
+                        loc: SourceLoc::of_token(line_i, line, token_i),
+                    });
                 }
-                _ => panic!("invalid data instruction")
+                code.push(instr);
             }
-        } 
+            Err((token_i, cause)) => {
+                errors.push(line_error(0, line_i, line, token_i, cause));
+                code.push(Instruction::Other { op: OtherOp::HALT });
+            }
+        }
+    }
+    while line_addrs.len() < program.split("\n").count() {
+        line_addrs.push(code.len() as u32);
+    }
+    // errors are sorted by their location in the source
+    errors.sort_by_key(|e| (e.line, e.column));
+    let object = ObjectFile {
+        name: name.to_string(),
+        code,
+        data,
+        data_align,
+        code_symbols,
+        data_symbols,
+        relocations,
+        line_addrs,
+    };
+    (object, errors)
+}
+
+/// assembles a single program into a relocatable object file
+pub fn assemble_object(program: &str, name: &str) -> Result<ObjectFile, Vec<AsmError>> {
+    let (object, errors) = assemble_object_with_errors(program, name);
+    if !errors.is_empty() {
+        return Err(errors);
     }
-    (data, data_table)
+    Ok(object)
 }
 
-pub fn assemble(program: &str) -> Executable{
+pub fn assemble(program: &str) -> Result<Executable, Vec<AsmError>>{
     assemble_and_link(vec![program])
 }
 
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Segment {
+    Code,
+    Data,
+}
+
+/// an immediate operand of the executable's code, or a word of its data, that holds absolute addresses
+/// the linker resolves addresses as if code & data are loaded at PROGRAM_INIT_ADDRESS & DATA_INIT_ADDRESS,
+/// a loader that places them elsewhere adds count * (actual base - default base) of the target segment
+#[derive(Debug, PartialEq, Clone)]
+pub struct LoadRelocation {
+    pub offset: u32, // relative address of the instruction or data word
+    pub segment: Segment, // segment of the patched word
+    pub target: Segment,
+    pub count: i32, // # of addresses of target summed in the operand, e.g 0 for a difference of 2 labels, which isn't relocated
+}
+
 pub struct Executable{
     pub code: Vec<Instruction>,
     pub data: Vec<i32>,
     pub symbol_table: HashMap<String, u32>,
     pub data_table: HashMap<String, u32>,
+    pub entry_point: u32, // relative address of the first instruction to execute
+    pub relocations: Vec<LoadRelocation>,
 }
 
-fn hashmaps_key_intersection(set1: &HashMap<String, u32>, set2: &HashMap<String, u32>) -> Vec<String>{
-    let keyset1 : HashSet<String> = set1.keys().into_iter().map(|s| s.clone()).collect();
-    let keyset2 : HashSet<String> = set2.keys().into_iter().map(|s| s.clone()).collect();
-    keyset1.intersection(&keyset2).into_iter().map(|s| s.clone()).collect()
+/// assembles the given programs & links them into a single executable
+/// returns all of the errors found in all of the programs on failure
+pub fn assemble_and_link(programs: Vec<&str>) -> Result<Executable, Vec<AsmError>> {
+    assemble_and_link_with_map(programs).map(|(exec, _)| exec)
 }
 
-pub fn assemble_and_link(programs: Vec<&str>) -> Executable {
-    let mut symbol_table = HashMap::new();
-    let mut data_table = HashMap::new();
-    let mut instructions = Vec::new();
-    let mut data = Vec::new();
-    let mut cur_rel_address = 0;
-    let mut cur_data_size = 0;
-
-    // create a symbol table for each program separately 
-    // and add it to global symbol table
-    // side note: we create a separate symobl table for each file instead of just concatenating all of the programs
-    // in order to be able to support source-level breakpoints in the future
-    for program in programs.iter(){
-        let (program_symbol_table, program_size) = gen_symbol_table(*program, cur_rel_address);
-        let (mut program_data, program_data_table) = extract_data(*program, cur_data_size);
-        cur_rel_address += program_size;
-        cur_data_size += program_data.len() as u32;
-        data.append(&mut program_data);
-        let symbol_intersect = hashmaps_key_intersection(&symbol_table, &program_symbol_table);
-        let data_intersect = hashmaps_key_intersection(&data_table, &program_data_table);
-        if symbol_intersect.len() != 0{
-            panic!("duplicate symbols between programs: {:?}", symbol_intersect);
-        }
-        if data_intersect.len() != 0{
-            panic!("duplicate data labels between programs: {:?}", data_intersect);
-        }
-        symbol_table.extend(program_symbol_table);
-        data_table.extend(program_data_table);
-    }
+/// like assemble_and_link, also returns the link map of the executable
+pub fn assemble_and_link_with_map(programs: Vec<&str>) -> Result<(Executable, LinkMap), Vec<AsmError>> {
     let whole_program = programs.join("\n");
     println!("--------");
     for (line_i, line) in whole_program.split("\n").collect::<Vec<&str>>().iter().enumerate(){
         println!("{}: {}", line_i, line);
     }
     println!("--------");
-    // second pass, parse instructions & calc relative offsets
-    cur_rel_address = 0;
-    let lines: Vec<&str> = whole_program.split("\n").collect();
-    for (line_i, line) in lines.iter().enumerate() {
-        symbol_table.insert(format!("_LINE_{}", line_i.to_string()), cur_rel_address); // for setting breakpoints in debugger
-        if let Some(instr) = maybe_parse_instruction(line, &symbol_table, &data_table, cur_rel_address) {
-            instructions.push(instr);
-            cur_rel_address += 1;
-        } else if !is_label(line) && !is_data(line) && line.trim().len() != 0 {
-            panic!("Invalid instruction: {}", line);
-        }
-    }
-    Executable{
-        code: instructions,
-        data,
-        symbol_table,
-        data_table,
+    let mut objects = Vec::new();
+    let mut errors = Vec::new();
+    for (program_i, program) in programs.iter().enumerate(){
+        let (object, object_errors) = assemble_object_with_errors(program, &format!("program {}", program_i));
+        errors.extend(object_errors.into_iter().map(|e| AsmError { program: program_i, ..e }));
+        objects.push(object);
+    }
+    // objects with errors are linked whole so that every undefined label gets reported
+    if errors.is_empty() {
+        objects = eliminate_dead_code(&objects);
+    }
+    match link_with_map(&objects) {
+        Ok(res) if errors.is_empty() => Ok(res),
+        Ok(_) => Err(errors),
+        Err(link_errors) => {
+            errors.extend(link_errors);
+            Err(errors)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::operating_system::layout::DATA_INIT_ADDRESS;
+    use crate::operating_system::linker::link;
     #[test]
     fn test_simple_program() {
         let program = "
@@ -193,7 +867,7 @@ mod tests {
         PUSH R2
         HALT
         ";
-        let exec = assemble(program);
+        let exec = assemble(program).unwrap();
         let isntructions = &exec.code;
         if let Instruction::Data {
             ref op,
@@ -258,7 +932,7 @@ mod tests {
         SUB R2 R2 R1
         TJMP L3
         ";
-        let exec = assemble(program);
+        let exec = assemble(program).unwrap();
 
         // println!("{:?}", symbol_table);
         assert_eq!(*exec.symbol_table.get("L1").unwrap(), 0);
@@ -285,7 +959,7 @@ mod tests {
         ADD R2 R2 2
         LOAD R2 R2
         ";
-        let exec = assemble(program);
+        let exec = assemble(program).unwrap();
         assert_eq!(exec.data.len(), 12);
         assert_eq!(*exec.data_table.get("s1").unwrap(), 0);
         assert_eq!(*exec.data_table.get("s2").unwrap(), 6);
@@ -294,4 +968,209 @@ mod tests {
         assert_eq!(exec.data[6] , 'w' as i32);
         assert_eq!(exec.data[11] , 0);
     }
+    #[test]
+    fn test_errors() {
+        let program = "
+        MOV R1 3
+        MOVE R1 3
+        L1:
+        ADD R1 R2
+        PUSH R9
+        JUMP L2
+        LEA R1 s1
+        ";
+        let errors = assemble(program).err().unwrap();
+        assert_eq!(errors.len(), 5);
+        assert_eq!(errors[0], AsmError{program: 0, line: 3, column: 9, cause: AsmErrorCause::UnknownMnemonic("MOVE".to_string())});
+        assert_eq!(errors[1], AsmError{program: 0, line: 5, column: 9, cause: AsmErrorCause::WrongOperandCount{expected: 3, found: 2}});
+        assert_eq!(errors[2], AsmError{program: 0, line: 6, column: 14, cause: AsmErrorCause::BadRegister("R9".to_string())});
+        assert_eq!(errors[3], AsmError{program: 0, line: 7, column: 14, cause: AsmErrorCause::UndefinedLabel("L2".to_string())});
+        assert_eq!(errors[4], AsmError{program: 0, line: 8, column: 16, cause: AsmErrorCause::UndefinedLabel("s1".to_string())});
+    }
+    #[test]
+    fn test_errors_across_programs() {
+        let program1 = "
+        F:
+        RET
+        ";
+        let program2 = "
+        .block b x
+        F:
+        RET
+        ";
+        let errors = assemble_and_link(vec![program1, program2]).err().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0], AsmError{program: 1, line: 2, column: 18, cause: AsmErrorCause::BadOperand("x".to_string())});
+        let cause = AsmErrorCause::MultipleDefinition { label: "F".to_string(), first: "program 0".to_string(), second: "program 1".to_string() };
+        assert_eq!(errors[1], AsmError{program: 1, line: 3, column: 9, cause});
+    }
+    #[test]
+    fn test_macros() {
+        let program = "
+        .equ ONE 1
+        .macro INC reg
+        ADD reg reg ONE
+        .endm
+        INC R1
+        INC R2
+        L1:
+        JUMP L1
+        ";
+        let exec = assemble(program).unwrap();
+        assert_eq!(exec.code.len(), 3);
+        assert_eq!(exec.code[1], Instruction::from_str("ADD R2 R2 1").unwrap());
+        assert_eq!(*exec.symbol_table.get("L1").unwrap(), 2);
+        // lines keep their source numbering, the second invocation starts at address 1
+        assert_eq!(*exec.symbol_table.get("_LINE_6").unwrap(), 1);
+        assert_eq!(*exec.symbol_table.get("_LINE_8").unwrap(), 2);
+    }
+    #[test]
+    fn test_visibility() {
+        let object = assemble_object("
+        .local helper buf
+        .weak F
+        .global G
+        .block buf 2
+        F:
+        CALL helper
+        G:
+        helper:
+        RET
+        ", "lib").unwrap();
+        assert_eq!(object.code_symbols.get("helper").unwrap().binding, Binding::Local);
+        assert_eq!(object.data_symbols.get("buf").unwrap().binding, Binding::Local);
+        assert_eq!(object.code_symbols.get("F").unwrap().binding, Binding::Weak);
+        assert_eq!(object.code_symbols.get("G").unwrap().binding, Binding::Global);
+        // a local symbol still starts a function
+        assert_eq!(object.code_symbols.get("F").unwrap().size, 1);
+        assert!(!object.exports(&RelocationKind::CodeOffset, "helper"));
+        let errors = assemble_object("
+        .weak
+        .local .Lx missing
+        .Lx:
+        RET
+        ", "bad").err().unwrap();
+        assert_eq!(errors[0].cause, AsmErrorCause::WrongOperandCount { expected: 1, found: 0 });
+        assert_eq!((errors[1].line, errors[1].column), (3, 16));
+        assert_eq!(errors[1].cause, AsmErrorCause::BadOperand(".Lx".to_string()));
+        assert_eq!(errors[2].cause, AsmErrorCause::UndefinedLabel("missing".to_string()));
+    }
+    #[test]
+    fn test_data_directives() {
+        let program = r#"
+        .string s "a:  b\t\"\x41\101\0"
+        .word table 1 -2 3
+        .fill 3 9
+        .align 4
+        .string "x"
+        "#;
+        let (lines, _) = expand_macros(program);
+        let (data, table, align, relocations) = extract_data(&lines).unwrap();
+        assert!(relocations.is_empty());
+        assert_eq!(data[..10], ['a' as i32, ':' as i32, ' ' as i32, ' ' as i32, 'b' as i32, 9, '"' as i32, 65, 65, 0]);
+        assert_eq!(*table.get("table").map(|symbol| &symbol.addr).unwrap(), 11);
+        assert_eq!(data[11..], [1, -2, 3, 9, 9, 9, 0, 0, 0, 'x' as i32, 0]);
+        assert_eq!(align, 4);
+        let (lines, _) = expand_macros("
+        .string s \"unterminated
+        .word w 1 x+
+        .fill f 2
+        .align 0
+        .word s+1
+        .word x
+        ");
+        let errors = extract_data(&lines).err().unwrap();
+        assert_eq!(errors.iter().map(|e| (e.line, e.column)).collect::<Vec<_>>(), vec![(2, 19), (3, 19), (4, 9), (5, 16), (6, 15), (7, 15)]);
+        assert_eq!(errors[4].cause, AsmErrorCause::BadOperand("s+1".to_string()));
+        // words that reference labels are left as 0 & relocated by the linker
+        let (lines, _) = expand_macros("
+        .word ptrs 7 s+1 end-s
+        ");
+        let (data, _, _, relocations) = extract_data(&lines).unwrap();
+        assert_eq!(data, vec![7, 0, 0]);
+        assert_eq!(relocations.iter().map(|reloc| (reloc.offset, reloc.segment, reloc.expr.to_str())).collect::<Vec<_>>(),
+            vec![(1, Segment::Data, "s+1".to_string()), (2, Segment::Data, "end-s".to_string())]);
+    }
+    #[test]
+    fn test_local_labels() {
+        let program = "
+        F:
+        1:
+        JUMP 1f
+        JUMP 1b
+        .Lloop:
+        JUMP .Lloop
+        1:
+        JUMP 1b
+        G:
+        .Lloop:
+        JUMP .Lloop
+        JUMP 2f
+        ";
+        let (mut lines, _) = expand_macros(program);
+        let errors = resolve_local_labels(&mut lines);
+        assert_eq!(errors, vec![AsmError{program: 0, line: 13, column: 14, cause: AsmErrorCause::UndefinedLabel("2f".to_string())}]);
+        let object = assemble_object(&program.replace("JUMP 2f", ""), "object").unwrap();
+        assert_eq!(object.code_symbols.get(".L1$1").unwrap().addr, 3);
+        assert_eq!(object.code_symbols.get(".Lloop$G").unwrap().binding, Binding::Local);
+        assert_eq!(object.code_symbols.get("G").unwrap().binding, Binding::Global);
+        let exec = link(&[object]).unwrap();
+        let expected = ["JUMP 3", "JUMP -1", "JUMP 0", "JUMP 0", "JUMP 0"];
+        for (instr, expected) in exec.code.iter().zip(expected.iter()) {
+            assert_eq!(*instr, Instruction::from_str(expected).unwrap());
+        }
+    }
+    #[test]
+    fn test_operand_expressions() {
+        assert_eq!(Expr::parse("-a+4-sizeof(b)").unwrap().terms, vec![
+            (-1, Term::Label("a".to_string())),
+            (1, Term::Const(4)),
+            (-1, Term::SizeOf("b".to_string())),
+        ]);
+        assert_eq!(Expr::parse("a-sizeof(b)").unwrap().to_str(), "a-sizeof(b)");
+        assert!(Expr::parse("a+").is_none());
+        assert!(Expr::parse("sizeof(a").is_none());
+        let program = "
+        .word table 1 2 3
+        .word 4
+        .stringz s hi
+        LEA R1 table+2
+        MOV R2 s-table
+        ADD R3 R3 sizeof(table)
+        TSTE R1 sizeof(F)
+        F:
+        JUMP F+1
+        HALT
+        ";
+        let exec = assemble(program).unwrap();
+        let expected = [
+            format!("LEA R1 {}", DATA_INIT_ADDRESS + 2),
+            "MOV R2 4".to_string(),
+            "ADD R3 R3 4".to_string(),
+            "TSTE R1 2".to_string(),
+            "JUMP 1".to_string(),
+        ];
+        for (instr, expected) in exec.code.iter().zip(expected.iter()) {
+            assert_eq!(*instr, Instruction::from_str(expected).unwrap());
+        }
+        let errors = assemble("MOV R1 a+*").err().unwrap();
+        assert_eq!(errors[0].cause, AsmErrorCause::BadOperand("a+*".to_string()));
+    }
+    #[test]
+    fn test_comments() {
+        assert_eq!(strip_comment("MOV R1 3 ; set R1: 3"), "MOV R1 3 ");
+        assert_eq!(strip_comment(".string s \"a;\\\"#\" # comment"), ".string s \"a;\\\"#\" ");
+        let program = "
+        ; a comment: not a label
+        # another one
+        .stringz s x ; y
+        L1: ; a label
+        LEA R1 s # load s
+        JUMP L1 # loop
+        ";
+        let exec = assemble(program).unwrap();
+        assert_eq!(exec.code.len(), 2);
+        assert_eq!(exec.data, vec!['x' as i32, 0]);
+        assert_eq!(*exec.symbol_table.get("L1").unwrap(), 0);
+    }
 }
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use crate::cpu::instructions::Register;
+use crate::cpu::{Memory, Registers};
+
+/*
+A backtrace walks the frames of the stack from the running function to main, see the stack frame in layout.rs:
+BP points at the saved BP of the caller & the return address is right above it.
+main's frame is the initial one, whose saved BP points at itself.
+A frame's address is of its current instruction, in the callers that's the CALL, one before the return address.
+*/
+
+const MAX_FRAMES: usize = 1000; // a corrupted stack may have a BP cycle
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub addr: u32,
+    pub function: Option<(String, u32)>, // name & offset of the address in it
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.function {
+            Some((name, offset)) => write!(f, "{}+{} (address {})", name, offset, self.addr),
+            None => write!(f, "?? (address {})", self.addr),
+        }
+    }
+}
+
+/// the frames from the innermost, function_at maps an address to its function
+/// stops early at a frame whose saved BP or return address can't be read, e.g a corrupt BP at the end of the memory
+pub fn backtrace(regs: &Registers, mem: &Memory, function_at: impl Fn(u32) -> Option<(String, u32)>) -> Vec<Frame> {
+    let frame = |addr: u32| Frame { addr, function: function_at(addr) };
+    let mut frames = vec![frame(regs.get(&Register::IR) as u32)];
+    let mut bp = regs.get(&Register::BP) as u32;
+    while frames.len() < MAX_FRAMES {
+        let (prev_bp, ret_addr) = match (mem.try_get_num(bp), bp.checked_add(1).map(|addr| mem.try_get_num(addr))) {
+            (Ok(prev_bp), Some(Ok(ret_addr))) => (prev_bp as u32, ret_addr as u32),
+            _ => break,
+        };
+        if prev_bp == bp || ret_addr == 0 {
+            break;
+        }
+        frames.push(frame(ret_addr - 1));
+        bp = prev_bp;
+    }
+    frames
+}
+
+/// the backtrace's frames, one per line
+pub fn format_backtrace(frames: &[Frame]) -> String {
+    frames.iter().enumerate().map(|(i, frame)| format!("#{} {}\n", i, frame)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{Cpu, MemEntry};
+    #[test]
+    fn test_backtrace() {
+        let mut cpu = Cpu::new();
+        cpu.regs.set(&Register::IR, 1010);
+        cpu.regs.set(&Register::BP, 100);
+        for (addr, value) in [(100, 110), (101, 1006), (110, 110), (111, 0)] {
+            cpu.mem.set(addr, MemEntry::Num(value));
+        }
+        let function_at = |addr: u32| if addr >= 1008 { Some(("f".to_string(), addr - 1008)) } else { None };
+        let frames = backtrace(&cpu.regs, &cpu.mem, function_at);
+        assert_eq!(format_backtrace(&frames), "#0 f+2 (address 1010)\n#1 ?? (address 1005)\n");
+
+        // a BP cycle stops at the frames limit
+        cpu.mem.set(110, MemEntry::Num(100));
+        cpu.mem.set(111, MemEntry::Num(1003));
+        assert_eq!(backtrace(&cpu.regs, &cpu.mem, function_at).len(), MAX_FRAMES);
+        // a saved BP of -1 ends the walk, the return address would be past the end of the memory
+        cpu.mem.set(110, MemEntry::Num(-1));
+        cpu.mem.set(u32::MAX, MemEntry::Num(100));
+        assert_eq!(backtrace(&cpu.regs, &cpu.mem, function_at).len(), 3);
+    }
+}
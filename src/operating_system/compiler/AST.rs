@@ -47,6 +47,7 @@ pub enum External {
     FuncDecl(FuncDecl),
     StructDecl(StructDecl),
     VarDecl(Decl),
+    Pragma(String), // the text after #pragma
 }
 
 impl External {
@@ -56,9 +57,10 @@ impl External {
             "Decl" => match node["type"]["_nodetype"].as_str().unwrap(){
                 "FuncDecl" => Ok(External::FuncDecl(FuncDecl::from(&node)?)),
                 "Struct" => Ok(External::StructDecl(StructDecl::from(&node)?)),
-                "TypeDecl" | "PtrDecl"=> Ok(External::VarDecl(Decl::from(&node)?)),
+                "TypeDecl" | "PtrDecl" | "ArrayDecl" => Ok(External::VarDecl(Decl::from(node)?)),
                 _ => panic!(),
                 }
+            "Pragma" => Ok(External::Pragma(node["string"].as_str().unwrap().to_string())),
             _ => {
                 panic!("Invalid external");
             }
@@ -83,6 +85,7 @@ pub struct FuncDecl {
     pub name: String,
     pub args: Vec<Decl>,
     pub ret_type: Type,
+    pub is_static: bool,
 }
 impl FuncDecl {
     fn from(node: &JsonNode) -> Result<FuncDecl, AstError> {
@@ -101,6 +104,7 @@ impl FuncDecl {
             name: node["name"].as_str().unwrap().to_string(),
             args: args,
             ret_type: Type::from(&node["type"]["type"]),
+            is_static: node["storage"].as_array().is_some_and(|storage| storage.iter().any(|s| s == "static")),
         })
     }
 }
@@ -108,16 +112,24 @@ impl FuncDecl {
 #[derive(Clone, Debug)]
 pub struct Compound {
     pub items: Vec<Statement>,
+    pub item_lines: Vec<Option<usize>>, // source line of each item, in the preprocessed program
     pub code_loc: String, // needed for scope id
 }
 
+// coords are of the form "path:line:column"
+fn coord_line(node: &JsonNode) -> Option<usize> {
+    node["coord"].as_str()?.rsplit(':').nth(1)?.parse().ok()
+}
+
 impl Compound {
     fn from(node: &JsonNode) -> Result<Compound, AstError> {
         let mut statements = Vec::new();
+        let mut item_lines = Vec::new();
         let node_type = node["_nodetype"].as_str().unwrap();
         if node_type == "ExprList" {
             for expr_node in node["exprs"].as_array().unwrap().iter() {
                 statements.push(Statement::Expression(Expression::from(expr_node)?));
+                item_lines.push(coord_line(expr_node));
             }
 
         }
@@ -126,6 +138,7 @@ impl Compound {
                 // we treat DeclList as a compound, because a declaration is also a statement
                 for decl_node in node["decls"].as_array().unwrap().iter() {
                     statements.push(Statement::from(&decl_node)?);
+                    item_lines.push(coord_line(decl_node));
                 }
             }
             else{
@@ -134,11 +147,13 @@ impl Compound {
                         // to avoid infinite recursion
                         if node_type != "Compound"{
                             statements.push(Statement::from(&node)?);
+                            item_lines.push(coord_line(node));
                         }
                     }
                     _ => {
                         for statement_node in node["block_items"].as_array().unwrap().iter() {
                             statements.push(Statement::from(&statement_node)?);
+                            item_lines.push(coord_line(statement_node));
                         }
                     }
                 }
@@ -146,6 +161,7 @@ impl Compound {
         }
         Ok(Compound {
              items: statements,
+             item_lines,
              code_loc: node["coord"].as_str().unwrap().to_string().replace(":", "-"),
         })
 
@@ -383,6 +399,7 @@ pub enum Expression {
     NameRef(NameRef),
     TypeName(TypeName), // used in sizeof()
     Cast(Cast),
+    InitList(Vec<Expression>), // brace enclosed initializer, e.g {1, {2, 3}}
 }
 
 impl Expression {
@@ -397,6 +414,13 @@ impl Expression {
             "ID" | "ArrayRef" | "StructRef" => Ok(Expression::NameRef(NameRef::from(&node)?)),
             "Typename" => Ok(Expression::TypeName(TypeName::from(&node)?)),
             "Cast" => Ok(Expression::Cast(Cast::from(&node)?)),
+            "InitList" => {
+                let mut exprs = Vec::new();
+                for expr in node["exprs"].as_array().unwrap().iter() {
+                    exprs.push(Expression::from(expr)?);
+                }
+                Ok(Expression::InitList(exprs))
+            },
             _ => {
                 panic!(format!(
                     "Invalid expression type:{}",
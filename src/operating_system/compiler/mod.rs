@@ -15,6 +15,7 @@ extern crate linked_hash_map;
 use linked_hash_map::LinkedHashMap;
 
 mod AST;
+mod peephole;
 mod preprocessor;
 
 use self::AST::*;
@@ -33,7 +34,7 @@ enum VarStorageType{
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum VariableType {
     Regular {_type: Type}, // including structs
     Array {_type: Box<VariableType>, dimentions: Vec<u32>},
@@ -103,33 +104,124 @@ pub struct StructData{
     items: LinkedHashMap<String, VariableData>,
 }
 
+/// value of a constant expression, evaluated at compile time for initializing globals
+#[derive(Debug, PartialEq)]
+enum ConstValue {
+    Int(i32),
+    Address(String, i32), // label + offset, relocated by the linker
+}
+
+impl ConstValue {
+    /// the operand of a .word directive that holds the value
+    fn to_word(&self) -> String {
+        match self {
+            ConstValue::Int(val) => val.to_string(),
+            ConstValue::Address(label, 0) => label.clone(),
+            ConstValue::Address(label, offset) if *offset < 0 => format!("{}{}", label, offset),
+            ConstValue::Address(label, offset) => format!("{}+{}", label, offset),
+        }
+    }
+}
+
+/// ascii value of a char constant, e.g 'a' or '\n'
+fn char_constant_value(val: &str) -> i32 {
+    let char_re = Regex::new(r"'(.+)'").unwrap();
+    let c = &char_re.captures(val).unwrap()[1];
+    let chars = &c.chars().collect::<Vec<char>>();
+    match chars.len() {
+        1 => chars[0] as u8 as i32,
+        2 => { // special chars
+            assert_eq!(chars[0], '\\');
+            match &chars[1] {
+                'n' => 10,
+                't' => 9,
+                _ => panic!("invalid special char"),
+            }
+        },
+        _ => panic!(),
+    }
+}
+
+/// value of an int constant, which may be hex or octal & have a suffix, e.g 0x1fU
+fn int_constant_value(val: &str) -> i32 {
+    let digits = val.trim_end_matches(|c| "uUlL".contains(c));
+    let parsed = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).map(|val| val as i32)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u32::from_str_radix(&digits[1..], 8).map(|val| val as i32)
+    } else {
+        digits.parse()
+    };
+    parsed.unwrap_or_else(|_| panic!("invalid int constant {}", val))
+}
+
+/// options that change the generated code
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    pub annotate_source: bool, // emit each C source line as a comment above the code generated for it
+    pub optimize: bool, // run the peephole optimizer over the generated code
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            annotate_source: false,
+            optimize: true,
+        }
+    }
+}
+
 pub struct Compiler {
     scope_to_data: HashMap<String, ScopeData>,
     func_to_data: HashMap<String, FuncData>,
     struct_to_data: HashMap<String, StructData>,
     data_val_to_label: HashMap<String, String>,
-    program_index: u32,  // hack to keep tmp labels from colliding accross different programs. OS is in charge of passing different indices
     cur_tmp_label: u32,
+    options: CompileOptions,
+    source_lines: Vec<String>, // of the preprocessed program
+    last_annotated_line: Option<usize>,
+    weak_symbols: HashSet<String>, // named by #pragma weak
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
 }
 
 impl Compiler {
-    pub fn new(program_i : u32) -> Compiler {
+    pub fn new() -> Compiler {
         Compiler {
             scope_to_data: HashMap::new(),
             func_to_data: HashMap::new(),
             struct_to_data: HashMap::new(),
             data_val_to_label: HashMap::new(),
-            program_index: program_i,
             cur_tmp_label: 0,
+            options: CompileOptions::default(),
+            source_lines: Vec::new(),
+            last_annotated_line: None,
+            weak_symbols: HashSet::new(),
         }
     }
 
+    // emits a source line as a comment, unless it was the last one emitted
+    fn maybe_annotate_source(&mut self, line: usize, code: &mut Vec<String>) {
+        if !self.options.annotate_source || self.last_annotated_line == Some(line) {
+            return;
+        }
+        if let Some(text) = self.source_lines.get(line - 1) {
+            code.push(format!("; {}", text.trim()));
+            self.last_annotated_line = Some(line);
+        }
+    }
+
+    // tmp labels are .L labels, local to the function (or program, for data) they're used in
     fn get_tmp_label(&self) -> String{
-        format!("{}_{}", self.program_index, self.cur_tmp_label)
+        self.cur_tmp_label.to_string()
     }
 
     fn get_global_label(&self) -> String{
-        format!("GLOBAL_{}", self.program_index)
+        ".LGLOBALS".to_string()
     }
 
     fn inc_tmp_label(&mut self){
@@ -146,9 +238,9 @@ impl Compiler {
 
     fn maybe_add_string_data(&mut self, s: &String, code: &mut Vec<String>) -> &String{
         if !self.data_val_to_label.contains_key(s) {
-            let label = format!("STR_{}", self.get_tmp_label());
+            let label = format!(".LSTR_{}", self.get_tmp_label());
             self.inc_tmp_label();
-            code.push(format!(".stringz {} {}", label, s));
+            code.push(format!(".string {} {}", label, s));
             self.data_val_to_label.insert(s.clone(), label);
         }
         self.data_val_to_label.get(s).unwrap()
@@ -163,32 +255,11 @@ impl Compiler {
                         code.push(format!("MOV R1 {}", const_val));
                     },
                     Type::Char => {
-                        // pasre char value & return ascii value
-                        let char_re = Regex::new(r"'(.+)'").unwrap();
-                        let c = &char_re.captures(&c.val).unwrap()[1];
-                        let chars = &c.chars().collect::<Vec<char>>(); 
-                        let val = match chars.len() {
-                            1 =>  {
-                                (chars[0] as u8)
-                            },
-                            2 => { // special chars
-                                assert_eq!(chars[0], '\\');
-                                match &chars[1] {
-                                    'n' => 10,
-                                    't' => 9,
-                                    _ => panic!("invalid special char"),
-                                }
-                            },
-                            _ => panic!(),
-                        };
-                        code.push(format!("MOV R1 {}", val));
+                        code.push(format!("MOV R1 {}", char_constant_value(&c.val)));
                     },
                     Type::_String => {
-                        // regex to remove string's quotes
-                        println!("unwrapping string from: {}", &c.val);
-                        let str_re = Regex::new(r#""(.+)""#).unwrap();
-                        let s = &str_re.captures(&c.val).unwrap()[1];
-                        let string_label = self.maybe_add_string_data(&s.to_string(), code);
+                        // the literal is emitted as is, quotes & escapes included
+                        let string_label = self.maybe_add_string_data(&c.val, code);
                         code.push(format!("LEA R1 {}", string_label));
                     }
                     _ => panic!("Invalid type for constant")
@@ -334,8 +405,8 @@ impl Compiler {
                 self.gen_assignment_code(ass, &scope, code);
             }
             Expression::TernaryOp(top) => {
-                let neg_label = format!("TERNARY_{}_NO", self.get_tmp_label());
-                let ternary_end_label = format!("TERNARY_{}_YES", self.get_tmp_label());
+                let neg_label = format!(".LTERNARY_{}_NO", self.get_tmp_label());
+                let ternary_end_label = format!(".LTERNARY_{}_YES", self.get_tmp_label());
                 self.inc_tmp_label();
                 self.right_gen(&top.cond, &scope, code);
                 code.push("TSTN R1 0".to_string());
@@ -389,7 +460,139 @@ impl Compiler {
                 // NOTE: in the current implementation casting has no actual effect
                 self.right_gen(&*cast.expr, scope, code);
             }
+            Expression::InitList(_) => {
+                panic!("initializer lists are only supported in global declarations");
+            }
+        }
+    }
+
+    /// evaluates the constant expression of a global's initializer
+    /// string literals & the addresses of globals evaluate to addresses, which the linker resolves
+    fn const_eval(&mut self, node: &Expression, code: &mut Vec<String>) -> ConstValue {
+        let global_scope = "_GLOBAL".to_string();
+        match node {
+            Expression::Constant(c) => match &c._type {
+                Type::Int => ConstValue::Int(int_constant_value(&c.val)),
+                Type::Char => ConstValue::Int(char_constant_value(&c.val)),
+                Type::_String => ConstValue::Address(self.maybe_add_string_data(&c.val, code).clone(), 0),
+                _ => panic!("Invalid type for constant"),
+            },
+            Expression::UnaryOp(op) => match &op.op_type {
+                UnaryopType::SIZEOF => match &*op.expr {
+                    Expression::TypeName(t) => ConstValue::Int(self.get_type_size(&t._type) as i32),
+                    _ => panic!("expression inside sizeof() must be a type"),
+                },
+                UnaryopType::REF => match &*op.expr {
+                    Expression::NameRef(NameRef::ID(id)) => {
+                        let var_data = self.find_variable(&id.name, &global_scope).unwrap_or_else(|| panic!("global {} not found", id.name));
+                        ConstValue::Address(self.get_global_label(), var_data.offset as i32)
+                    },
+                    _ => panic!("only the address of a global is a constant"),
+                },
+                UnaryopType::NEG | UnaryopType::NOT => match (&op.op_type, self.const_eval(&op.expr, code)) {
+                    (UnaryopType::NEG, ConstValue::Int(val)) => ConstValue::Int(val.wrapping_neg()),
+                    (_, ConstValue::Int(val)) => ConstValue::Int((val == 0) as i32),
+                    _ => panic!("invalid operand of a unary op in a constant expression"),
+                },
+                _ => panic!("not a constant expression"),
+            },
+            Expression::BinaryOp(op) => {
+                let left = self.const_eval(&op.left, code);
+                let right = self.const_eval(&op.right, code);
+                match (left, right) {
+                    (ConstValue::Int(l), ConstValue::Int(r)) => ConstValue::Int(match op.op_type {
+                        BinaryopType::ADD => l.wrapping_add(r),
+                        BinaryopType::SUB => l.wrapping_sub(r),
+                        BinaryopType::MUL => l.wrapping_mul(r),
+                        BinaryopType::DIV | BinaryopType::MOD if r == 0 => panic!("division by zero in a constant expression"),
+                        BinaryopType::DIV => l.wrapping_div(r),
+                        BinaryopType::MOD => l.wrapping_rem(r),
+                        BinaryopType::AND => l & r,
+                        BinaryopType::OR => l | r,
+                        BinaryopType::XOR => l ^ r,
+                        BinaryopType::SHL => l.wrapping_shl(r as u32),
+                        BinaryopType::SHR => l.wrapping_shr(r as u32),
+                        BinaryopType::EQ => (l == r) as i32,
+                        BinaryopType::NEQ => (l != r) as i32,
+                        BinaryopType::LogicalAnd => (l != 0 && r != 0) as i32,
+                        BinaryopType::LogicalOr => (l != 0 || r != 0) as i32,
+                        BinaryopType::LT => (l < r) as i32,
+                        BinaryopType::LTEQ => (l <= r) as i32,
+                        BinaryopType::GT => (l > r) as i32,
+                        BinaryopType::GTEQ => (l >= r) as i32,
+                    }),
+                    // as in the generated code, pointer arithmetic isn't scaled by the size of the pointed type
+                    (ConstValue::Address(label, offset), ConstValue::Int(r)) if op.op_type == BinaryopType::ADD => ConstValue::Address(label, offset + r),
+                    (ConstValue::Address(label, offset), ConstValue::Int(r)) if op.op_type == BinaryopType::SUB => ConstValue::Address(label, offset - r),
+                    (ConstValue::Int(l), ConstValue::Address(label, offset)) if op.op_type == BinaryopType::ADD => ConstValue::Address(label, offset + l),
+                    _ => panic!("invalid operands of a binary op in a constant expression"),
+                }
+            },
+            Expression::TernaryOp(op) => match self.const_eval(&op.cond, code) {
+                ConstValue::Int(0) => self.const_eval(&op.iffalse, code),
+                ConstValue::Int(_) => self.const_eval(&op.iftrue, code),
+                _ => panic!("the condition of a constant ternary op must be a number"),
+            },
+            // an array evaluates to its address
+            Expression::NameRef(NameRef::ID(id)) => {
+                let var_data = self.find_variable(&id.name, &global_scope).unwrap_or_else(|| panic!("global {} not found", id.name));
+                match var_data.var_type {
+                    VariableType::Array{..} => ConstValue::Address(self.get_global_label(), var_data.offset as i32),
+                    VariableType::Regular{..} => panic!("the value of global {} isn't a constant", id.name),
+                }
+            },
+            Expression::Cast(cast) => self.const_eval(&cast.expr, code),
+            _ => panic!("initializer of a global must be a constant expression"),
+        }
+    }
+
+    fn get_var_type_size(&self, var_type: &VariableType) -> u32 {
+        match var_type {
+            VariableType::Regular{_type} => self.get_type_size(_type),
+            VariableType::Array{_type, dimentions} => dimentions.iter().product::<u32>() * self.get_array_item_size(_type),
+        }
+    }
+
+    /// the words of a global of the given type, initialized by init
+    /// missing items of arrays & fields of structs are zeroed, nested arrays may be initialized by a flat list
+    fn global_init_words(&mut self, var_type: &VariableType, init: &Expression, code: &mut Vec<String>) -> Vec<String> {
+        let size = self.get_var_type_size(var_type) as usize;
+        let mut words = Vec::new();
+        match (var_type, init) {
+            (VariableType::Array{_type, dimentions}, Expression::InitList(exprs)) => {
+                let sub_array = VariableType::Array{_type: _type.clone(), dimentions: dimentions[1..].to_vec()};
+                let row_type = if dimentions.len() > 1 { &sub_array } else { &**_type };
+                for expr in exprs.iter() {
+                    // items that aren't braced initialize the next item of the innermost array
+                    let expr_type = if let Expression::InitList(_) = expr { row_type } else { &**_type };
+                    let expr_words = self.global_init_words(expr_type, expr, code);
+                    words.extend(expr_words);
+                }
+            },
+            (VariableType::Regular{_type: Type::Struct(struct_name)}, Expression::InitList(exprs)) => {
+                let struct_data = self.struct_to_data.get(struct_name).expect("struct doesn't exist");
+                let field_types: Vec<VariableType> = struct_data.items.values().map(|field| field.var_type.clone()).collect();
+                if exprs.len() > field_types.len() {
+                    panic!("too many initializers for struct {}", struct_name);
+                }
+                for (field_type, expr) in field_types.iter().zip(exprs.iter()) {
+                    let field_words = self.global_init_words(field_type, expr, code);
+                    words.extend(field_words);
+                }
+            },
+            (VariableType::Regular{..}, Expression::InitList(exprs)) if exprs.len() == 1 => {
+                words = self.global_init_words(var_type, &exprs[0], code);
+            },
+            (VariableType::Regular{..}, Expression::InitList(_)) => panic!("a scalar must be initialized by a single expression"),
+            (VariableType::Regular{_type: Type::Struct(_)}, _) => panic!("a struct must be initialized by a brace enclosed list"),
+            (VariableType::Regular{..}, _) => words.push(self.const_eval(init, code).to_word()),
+            (VariableType::Array{..}, _) => panic!("an array must be initialized by a brace enclosed list"),
         }
+        if words.len() > size {
+            panic!("too many initializers");
+        }
+        words.resize(size, "0".to_string());
+        words
     }
 
     /// generates code for name reference
@@ -503,7 +706,7 @@ impl Compiler {
                 // let mut offset = 0;                        
                 code.push("MOV R2 R1".to_string()); // R2 holds current item addr
                 let mut cur_dimentions_product = 1;
-                let item_size = self.get_array_item_size(item_type);
+                let item_size = self.get_array_item_size(&item_type);
 
                 // hiding from the borrow checker
                 let indices = array_ref.indices.clone();
@@ -558,8 +761,7 @@ impl Compiler {
                 code.push(format!("ADD R1 BP {}", bp_offset));
             },
             VarStorageType::Global => {
-                code.push(format!("LEA R1 {}", self.get_global_label()));
-                code.push(format!("ADD R1 R1 {}", &var_data.offset));
+                code.push(format!("LEA R1 {}+{}", self.get_global_label(), &var_data.offset));
             }
         };
         var_data
@@ -601,6 +803,17 @@ impl Compiler {
                             next_var_offset += &var_data.size;
                             glob_vars.insert(var_data.name.clone(), var_data);
                         },
+                        // structs are registered in order, before the globals of their type
+                        External::StructDecl(struct_decl) => {
+                            self.register_struct(struct_decl);
+                        },
+                        // other pragmas are ignored, as C allows
+                        External::Pragma(pragma) => {
+                            let tokens: Vec<&str> = pragma.split_whitespace().collect();
+                            if tokens.len() == 2 && tokens[0] == "weak" {
+                                self.weak_symbols.insert(tokens[1].to_string());
+                            }
+                        },
                         _ => {},
                     }
                 }
@@ -615,8 +828,27 @@ impl Compiler {
                     break_label: None,
                     continue_label: None
                 });
+                // initializers are evaluated at compile time & emitted as the initial data of the globals
+                let mut global_words = vec!["0".to_string(); next_var_offset as usize];
+                for ext in root_node.externals.iter(){
+                    let (name, init) = match ext{
+                        External::VarDecl(Decl::VarDecl(var_decl)) => (&var_decl.name, var_decl.init.clone()),
+                        External::VarDecl(Decl::ArrayDecl(arr_decl)) => (&arr_decl.name, arr_decl.init.clone().map(Expression::InitList)),
+                        _ => continue,
+                    };
+                    if let Some(init) = init {
+                        let var_data = self.find_variable(name, &"_GLOBAL".to_string()).unwrap();
+                        let (offset, var_type) = (var_data.offset as usize, var_data.var_type.clone());
+                        let words = self.global_init_words(&var_type, &init, code);
+                        global_words.splice(offset..offset + words.len(), words);
+                    }
+                }
                 let global_label = self.get_global_label();
-                code.push(format!(".block {} {}", global_label, next_var_offset));
+                if global_words.iter().all(|word| word == "0") {
+                    code.push(format!(".block {} {}", global_label, next_var_offset));
+                } else {
+                    code.push(format!(".word {} {}", global_label, global_words.join(" ")));
+                }
                 code.push("JUMP main".to_string());
                 for ext in root_node.externals.iter(){
                     match ext{
@@ -626,10 +858,7 @@ impl Compiler {
                         External::FuncDecl(func_decl) => {
                             self.code_gen(AstNode::FuncDecl(func_decl), &"_GLOBAL".to_string(), code);
                         },
-                        External::StructDecl(struct_decl) => {
-                            self.register_struct(struct_decl);
-                        },
-                        External::VarDecl(_) => {},
+                        External::VarDecl(_) | External::StructDecl(_) | External::Pragma(_) => {},
                     };
                 }
             },
@@ -641,6 +870,12 @@ impl Compiler {
             }
             AstNode::FuncDef(func_def) => {
                 let func_name = &func_def.decl.name;
+                // static functions are local to the object, weak ones may be overridden by another object
+                if func_def.decl.is_static {
+                    code.push(format!(".local {}", func_name));
+                } else if self.weak_symbols.contains(func_name) {
+                    code.push(format!(".weak {}", func_name));
+                }
                 code.push(format!("{}:", func_name));
                 self.register_func_decl(&func_def.decl);
                 self.register_func_body(&func_def.body, &func_def.decl, scope);
@@ -665,7 +900,7 @@ impl Compiler {
 
                 self.code_gen(AstNode::Compound(&func_def.body), &func_name, code);
 
-                code.push(format!("_{}_END:", func_name));
+                code.push(".LEND:".to_string());
 
                 // restore registers
                 let func_data = self.get_func_data(&func_name).unwrap();
@@ -684,7 +919,10 @@ impl Compiler {
                 code.push("RET".to_string());
             }
             AstNode::Compound(compound) => {
-                for item in compound.items.iter() {
+                for (item, line) in compound.items.iter().zip(compound.item_lines.iter()) {
+                    if let Some(line) = line {
+                        self.maybe_annotate_source(*line, code);
+                    }
                     self.code_gen(AstNode::Statement(&item), &scope, code);
                 }
             }
@@ -696,7 +934,7 @@ impl Compiler {
                             code.push("ADD R2 BP 2".to_string());
                             code.push("STR R2 R1 ".to_string());
                         }
-                        code.push(format!("JUMP _{}_END", self.get_scope_data(scope).unwrap().parent_func));
+                        code.push("JUMP .LEND".to_string());
                     }
                     Statement::Decl(decl) => {
                         match decl{
@@ -728,8 +966,8 @@ impl Compiler {
                         self.right_gen(&exp, &scope, code);
                     }
                     Statement::If(if_stmt) => {
-                        let else_label = format!("IF_{}_ELSE", self.get_tmp_label());
-                        let if_end_label = format!("IF_{}_END", self.get_tmp_label());
+                        let else_label = format!(".LIF_{}_ELSE", self.get_tmp_label());
+                        let if_end_label = format!(".LIF_{}_END", self.get_tmp_label());
                         self.inc_tmp_label();
                         self.right_gen(&if_stmt.cond, &scope, code);
                         code.push("TSTN R1 0".to_string());
@@ -749,8 +987,8 @@ impl Compiler {
                         self.code_gen(AstNode::Compound(&comp), &comp.code_loc, code);
                     },
                     Statement::WhileLoop(wl) => {
-                        let while_start = format!("WHILE_{}_START", self.get_tmp_label());
-                        let while_end = format!("WHILE_{}_END", self.get_tmp_label());
+                        let while_start = format!(".LWHILE_{}_START", self.get_tmp_label());
+                        let while_end = format!(".LWHILE_{}_END", self.get_tmp_label());
                         self.inc_tmp_label();
                         self.update_scope_break_continue_labels(&wl.code_loc, &while_end, &while_start);
                         code.push(format!("{}:", while_start));
@@ -762,9 +1000,9 @@ impl Compiler {
                         code.push(format!("{}:", while_end));
                     },
                     Statement::DoWhileLoop(dwl) => {
-                        let dowhile_cond = format!("DOWHILE_{}_COND", self.get_tmp_label());
-                        let dowhile_body = format!("DOWHILE_{}_BODY", self.get_tmp_label());
-                        let dowhile_end = format!("DOWHILE_{}_END", self.get_tmp_label());
+                        let dowhile_cond = format!(".LDOWHILE_{}_COND", self.get_tmp_label());
+                        let dowhile_body = format!(".LDOWHILE_{}_BODY", self.get_tmp_label());
+                        let dowhile_end = format!(".LDOWHILE_{}_END", self.get_tmp_label());
                         self.inc_tmp_label();
                         self.update_scope_break_continue_labels(&dwl.code_loc, &dowhile_end, &dowhile_cond);
                         code.push(format!("JUMP {}", dowhile_body));
@@ -778,9 +1016,9 @@ impl Compiler {
                         code.push(format!("{}:", dowhile_end));
                     },
                     Statement::ForLoop(fl) => {
-                        let for_cond = format!("FOR_{}_COND", self.get_tmp_label());
-                        let for_end = format!("FOR_{}_END", self.get_tmp_label());
-                        let for_next = format!("FOR_{}_NEXT", self.get_tmp_label());
+                        let for_cond = format!(".LFOR_{}_COND", self.get_tmp_label());
+                        let for_end = format!(".LFOR_{}_END", self.get_tmp_label());
+                        let for_next = format!(".LFOR_{}_NEXT", self.get_tmp_label());
                         self.inc_tmp_label();
                         self.update_scope_break_continue_labels(&fl.code_loc, &for_end, &for_next);
                         if let Some(init) = &fl.init{
@@ -918,15 +1156,24 @@ impl Compiler {
         }
     }
 
+    /// offset of the lowest address of a variable that starts at start
+    /// locals grow down from BP so their lowest address is their last word, globals grow up from the globals label
+    fn var_offset(local_or_arg: &VarStorageType, start: u32, size: u32) -> u32 {
+        match local_or_arg {
+            VarStorageType::Global => start,
+            _ => start + size - 1,
+        }
+    }
+
     fn variable_data_from_decl(&self, decl: &Decl, local_or_arg: VarStorageType, offset: &u32) -> VariableData{
         match decl{
             Decl::VarDecl(var_decl) => {
                 let size = self.get_decl_size(decl);
                 VariableData{
                     name: var_decl.name.clone(),
+                    offset: Self::var_offset(&local_or_arg, *offset, size),
                     local_or_arg: local_or_arg,
                     var_type: VariableType::from(decl),
-                    offset: *offset + size - 1,
                     size: size.clone(),
                 }
             },
@@ -934,9 +1181,9 @@ impl Compiler {
                 let size = self.get_array_size(&arr_decl._type, &arr_decl.dimentions);
                 VariableData{
                     name: arr_decl.name.clone(),
+                    offset: Self::var_offset(&local_or_arg, *offset, size),
                     local_or_arg: local_or_arg,
                     var_type: VariableType::from(decl),
-                    offset: *offset + size - 1,
                     size: size,
                 }
             },
@@ -1085,6 +1332,7 @@ impl Compiler {
 
     fn _compile(&mut self, path_to_c_source: &str) -> Vec<String> {
         let program = preprocessor::preprocess(path_to_c_source);
+        self.source_lines = program.split("\n").map(|line| line.to_string()).collect();
 
         let mut tmpfile = tempfile::Builder::new().suffix(".c").tempfile().unwrap();
         write!(tmpfile, "{}", &program.as_str()).unwrap();
@@ -1096,9 +1344,17 @@ impl Compiler {
         code
     }
 
-    pub fn compile(path_to_c_source: &str, program_index: u32) -> String {
-        let mut instance = Compiler::new(program_index);
-        let instructions = instance._compile(path_to_c_source);
+    pub fn compile(path_to_c_source: &str) -> String {
+        Compiler::compile_with_options(path_to_c_source, &CompileOptions::default())
+    }
+
+    pub fn compile_with_options(path_to_c_source: &str, options: &CompileOptions) -> String {
+        let mut instance = Compiler::new();
+        instance.options = options.clone();
+        let mut instructions = instance._compile(path_to_c_source);
+        if options.optimize {
+            instructions = peephole::optimize(instructions);
+        }
         instructions.join("\n")
     }
 }
@@ -1108,7 +1364,7 @@ mod tests{
     use super::*;
     #[test]
     fn find_variable(){
-        let mut compiler = Compiler::new(0);
+        let mut compiler = Compiler::new();
         compiler._compile("tests/compiler_test_data/variables/inputs/assign.c");
         let _a_var = compiler.find_variable(&"a".to_string(), &"main".to_string()).unwrap();
         let b_var = compiler.find_variable(&"b".to_string(), &"main".to_string());
@@ -1116,7 +1372,7 @@ mod tests{
     }
     #[test] #[ignore]
     fn find_nested_scope(){
-        let mut compiler = Compiler::new(0);
+        let mut compiler = Compiler::new();
         compiler._compile("tests/compiler_test_data/scopes/inputs/declare_block.c");
         println!("{:?}", compiler.scope_to_data);
         assert_eq!(compiler.scope_to_data.len(), 3);
@@ -1128,21 +1384,21 @@ mod tests{
     #[test] #[ignore]
 
     fn find_break_continue_labels(){
-        let mut compiler = Compiler::new(0);
+        let mut compiler = Compiler::new();
         compiler._compile("tests/compiler_test_data/loops/inputs/while_multi_statement.c");
         println!("{:?}", compiler.scope_to_data);
         assert_eq!(compiler.scope_to_data.len(), 3);
         match compiler.find_break_continue_labels(&"tests/compiler_test_data/loops/inputs/while_multi_statement.c-5-5".to_string()){
             Some((break_label, continue_label)) => {
-                assert_eq!(break_label, "WHILE_0_END");
-                assert_eq!(continue_label, "WHILE_0_START");
+                assert_eq!(break_label, ".LWHILE_0_END");
+                assert_eq!(continue_label, ".LWHILE_0_START");
             },
             _ => panic!()
         }
     }
     #[test]
     fn function_args(){
-        let mut compiler = Compiler::new(0);
+        let mut compiler = Compiler::new();
         compiler._compile("tests/compiler_test_data/functions/inputs/multi_arg.c");
         println!("{:?}", compiler.scope_to_data);
         let func_data = compiler.get_func_data(&"sub_3".to_string()).unwrap();
@@ -1177,7 +1433,7 @@ mod tests{
 
     #[test]
     fn struct_registration(){
-        let mut compiler = Compiler::new(0);
+        let mut compiler = Compiler::new();
         compiler._compile("tests/compiler_test_data/structs/inputs/1.c");
         let struct_data = compiler.struct_to_data.get("A").unwrap();
         assert_eq!(struct_data.name, "A");
@@ -1195,5 +1451,16 @@ mod tests{
         assert_eq!(struct_data.items.get("z").unwrap().offset, 2);
     }
 
+    #[test]
+    fn global_initializers(){
+        let mut compiler = Compiler::new();
+        let code = compiler._compile("tests/compiler_test_data/globals/inputs/3.c");
+        let globals = code.iter().find(|line| line.starts_with(".word .LGLOBALS")).unwrap();
+        assert_eq!(globals, ".word .LGLOBALS 5 1 2 0 3 4 5 -1 7 .LSTR_0 2 3 5 0 .LGLOBALS+12 18");
+        assert_eq!(int_constant_value("0x1fU"), 31);
+        assert_eq!(int_constant_value("010"), 8);
+        assert_eq!(ConstValue::Address(".LSTR_0".to_string(), -1).to_word(), ".LSTR_0-1");
+    }
+
 
 }
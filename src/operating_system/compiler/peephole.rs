@@ -0,0 +1,395 @@
+use crate::cpu::instructions::*;
+use crate::operating_system::assembler::strip_comment;
+use std::str::FromStr;
+
+/*
+Peephole optimizer over the generated assembly, runs between the compiler & the assembler.
+Patterns are matched across comments & directives but never across labels, since a label may be jumped to:
+- PUSH Ra, register only instructions that don't use Rb or SP, POP Rb => MOV Rb Ra, the instructions (nothing if Ra == Rb)
+- TSTx, MOV Rk ZR, TSTN Rk 0, TJMP/FJMP L => TSTx, TJMP/FJMP L if Rk isn't read before being written on both paths
+- POP ZR repeated n > 1 times => ADD SP SP n if ZR isn't read before being written, a CALL or a RET
+- MOV Ra Ra => nothing
+- JUMP L directly followed by L: => L:
+PUSH ZR chains that make space for locals are kept, a local that's read before being written has to exist in memory.
+*/
+
+enum Line {
+    Label(String),
+    Instruction(Instruction),
+    Other, // comments, directives & empty lines
+}
+
+/// parses a line of generated assembly, label operands are parsed as 0
+fn parse(line: &str) -> Line {
+    let line = strip_comment(line).trim();
+    if line.ends_with(':') && !line.contains(char::is_whitespace) {
+        return Line::Label(line.trim_end_matches(':').to_string());
+    }
+    if line.is_empty() || line.starts_with('.') {
+        return Line::Other;
+    }
+    let tokens: Vec<&str> = line.split_whitespace()
+        .enumerate()
+        .map(|(i, token)| if i == 0 || Register::from_str(token).is_ok() || token.parse::<i32>().is_ok() { token } else { "0" })
+        .collect();
+    match Instruction::from_str(&tokens.join(" ")) {
+        Ok(instr) => Line::Instruction(instr),
+        Err(_) => Line::Other,
+    }
+}
+
+fn reg_or_imm_is(arg: &RegOrImm, reg: &Register) -> bool {
+    matches!(arg, RegOrImm::Reg(arg) if arg == reg)
+}
+
+fn reads(instr: &Instruction, reg: &Register) -> bool {
+    match instr {
+        Instruction::UnaryArith { arg, .. } => arg == reg,
+        Instruction::BinArith { arg1, arg2, .. } => arg1 == reg || reg_or_imm_is(arg2, reg),
+        Instruction::Data { op: DataOp::STR, dst, src } => dst == reg || reg_or_imm_is(src, reg),
+        Instruction::Data { src, .. } => reg_or_imm_is(src, reg),
+        Instruction::Stack { op: StackOp::PUSH, dst } => dst == reg || *reg == Register::SP,
+        Instruction::Stack { .. } => *reg == Register::SP,
+        Instruction::Test { arg1, arg2, .. } => arg1 == reg || reg_or_imm_is(arg2, reg),
+        Instruction::Flow { .. } => true,
+        Instruction::Other { .. } => true,
+    }
+}
+
+fn writes(instr: &Instruction, reg: &Register) -> bool {
+    match instr {
+        Instruction::UnaryArith { arg, .. } => arg == reg,
+        Instruction::BinArith { dst, .. } => dst == reg,
+        Instruction::Data { op: DataOp::STR, .. } => false,
+        Instruction::Data { dst, .. } => dst == reg,
+        Instruction::Stack { op: StackOp::POP, dst } => dst == reg || *reg == Register::SP,
+        Instruction::Stack { .. } => *reg == Register::SP,
+        Instruction::Test { .. } => *reg == Register::ZR,
+        Instruction::Flow { .. } | Instruction::Other { .. } => true,
+    }
+}
+
+/// whether an instruction only reads & writes registers, i.e it can be moved across a push or a pop
+fn is_register_only(instr: &Instruction) -> bool {
+    match instr {
+        Instruction::UnaryArith { .. } | Instruction::BinArith { .. } => true,
+        Instruction::Data { op, .. } => *op != DataOp::STR,
+        _ => false,
+    }
+}
+
+struct Optimizer {
+    lines: Vec<String>,
+    parsed: Vec<Line>,
+}
+
+impl Optimizer {
+    fn instr(&self, line_i: usize) -> Option<&Instruction> {
+        match &self.parsed[line_i] {
+            Line::Instruction(instr) => Some(instr),
+            _ => None,
+        }
+    }
+
+    fn remove(&mut self, line_i: usize) {
+        self.lines.remove(line_i);
+        self.parsed.remove(line_i);
+    }
+
+    fn replace(&mut self, line_i: usize, line: String) {
+        self.parsed[line_i] = parse(&line);
+        self.lines[line_i] = line;
+    }
+
+    fn tokens(&self, line_i: usize) -> Vec<&str> {
+        strip_comment(&self.lines[line_i]).split_whitespace().collect()
+    }
+
+    /// index of the next label or instruction after line_i
+    fn next(&self, line_i: usize) -> Option<usize> {
+        (line_i + 1..self.lines.len()).find(|i| !matches!(self.parsed[*i], Line::Other))
+    }
+
+    fn label_line(&self, label: &str) -> Option<usize> {
+        let mut defs = (0..self.lines.len()).filter(|i| matches!(&self.parsed[*i], Line::Label(l) if l == label));
+        match (defs.next(), defs.next()) {
+            (Some(line_i), None) => Some(line_i),
+            _ => None, // undefined, or a label that's defined once per function
+        }
+    }
+
+    /// whether reg is written before it's read when execution continues at line_i
+    /// flow instructions end the search, reg is then assumed to be live
+    fn is_dead(&self, reg: &Register, line_i: usize) -> bool {
+        for i in line_i..self.lines.len() {
+            if let Some(instr) = self.instr(i) {
+                if reads(instr, reg) {
+                    return false;
+                }
+                if writes(instr, reg) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// like is_dead for ZR, which isn't live across a CALL or a RET,
+    /// since the generated code reads it only right after the test that sets it
+    fn is_flag_dead(&self, line_i: usize) -> bool {
+        for i in line_i..self.lines.len() {
+            match self.instr(i) {
+                Some(Instruction::Flow { op: FlowOp::CALL, .. }) | Some(Instruction::Other { op: OtherOp::RET }) => return true,
+                Some(instr) if reads(instr, &Register::ZR) => return false,
+                Some(instr) if writes(instr, &Register::ZR) => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// tries the patterns that start at line_i, returns whether the lines were changed
+    fn optimize_at(&mut self, line_i: usize) -> bool {
+        let instr = match self.instr(line_i) {
+            Some(instr) => instr.clone(),
+            None => return false,
+        };
+        match instr {
+            Instruction::Data { op: DataOp::MOV, dst, src: RegOrImm::Reg(src) } if dst == src => {
+                self.remove(line_i);
+                true
+            }
+            Instruction::Flow { op: FlowOp::JUMP, .. } => {
+                let target = self.tokens(line_i)[1].to_string();
+                let mut i = line_i;
+                while let Some(next) = self.next(i) {
+                    match &self.parsed[next] {
+                        Line::Label(label) if *label == target => {
+                            self.remove(line_i);
+                            return true;
+                        }
+                        Line::Label(_) => i = next,
+                        _ => break,
+                    }
+                }
+                false
+            }
+            Instruction::Stack { op: StackOp::PUSH, dst: pushed } => self.push_pop(line_i, pushed),
+            Instruction::Stack { op: StackOp::POP, dst: Register::ZR } => {
+                let mut pops = vec![line_i];
+                while let Some(next) = self.next(*pops.last().unwrap()) {
+                    match self.instr(next) {
+                        Some(Instruction::Stack { op: StackOp::POP, dst: Register::ZR }) => pops.push(next),
+                        _ => break,
+                    }
+                }
+                // the chain leaves the last popped word in ZR
+                if pops.len() < 2 || !self.is_flag_dead(pops.last().unwrap() + 1) {
+                    return false;
+                }
+                for pop_i in pops[1..].iter().rev() {
+                    self.remove(*pop_i);
+                }
+                self.replace(line_i, format!("ADD SP SP {}", pops.len()));
+                true
+            }
+            Instruction::Test { .. } => self.flag_move(line_i),
+            _ => false,
+        }
+    }
+
+    /// PUSH Ra, register only instructions, POP Rb => MOV Rb Ra, the instructions
+    fn push_pop(&mut self, push_i: usize, pushed: Register) -> bool {
+        if pushed == Register::SP {
+            return false;
+        }
+        let mut between = Vec::new();
+        let mut i = push_i;
+        let (pop_i, popped) = loop {
+            i = match self.next(i) {
+                Some(next) => next,
+                None => return false,
+            };
+            match self.instr(i) {
+                Some(Instruction::Stack { op: StackOp::POP, dst }) => break (i, dst.clone()),
+                Some(instr) if is_register_only(instr) => between.push(instr),
+                _ => return false,
+            }
+        };
+        let conflicts = |instr: &&Instruction| {
+            [&popped, &Register::SP].iter().any(|reg| reads(instr, reg) || writes(instr, reg))
+        };
+        if popped == Register::SP || between.iter().any(conflicts) {
+            return false;
+        }
+        self.remove(pop_i);
+        if pushed == popped {
+            self.remove(push_i);
+        } else {
+            self.replace(push_i, format!("MOV {} {}", popped.to_str(), pushed.to_str()));
+        }
+        true
+    }
+
+    /// TSTx, MOV Rk ZR, TSTN Rk 0, TJMP/FJMP L => TSTx, TJMP/FJMP L
+    /// ZR already holds 0 or 1 after the first test, so the second one only copies it to Rk
+    fn flag_move(&mut self, test_i: usize) -> bool {
+        let mov_i = match self.next(test_i) {
+            Some(i) => i,
+            None => return false,
+        };
+        let reg = match self.instr(mov_i) {
+            Some(Instruction::Data { op: DataOp::MOV, dst, src: RegOrImm::Reg(Register::ZR) }) => dst.clone(),
+            _ => return false,
+        };
+        let tst_i = match self.next(mov_i) {
+            Some(i) => i,
+            None => return false,
+        };
+        match self.instr(tst_i) {
+            Some(Instruction::Test { op: TestOp::TSTN, arg1, arg2: RegOrImm::Val(0) }) if *arg1 == reg => {}
+            _ => return false,
+        }
+        let jump_i = match self.next(tst_i) {
+            Some(i) => i,
+            None => return false,
+        };
+        match self.instr(jump_i) {
+            Some(Instruction::Flow { op: FlowOp::TJMP | FlowOp::FJMP, .. }) => {}
+            _ => return false,
+        }
+        let target = match self.label_line(self.tokens(jump_i)[1]) {
+            Some(target) => target,
+            None => return false,
+        };
+        if !self.is_dead(&reg, jump_i + 1) || !self.is_dead(&reg, target) {
+            return false;
+        }
+        self.remove(tst_i);
+        self.remove(mov_i);
+        true
+    }
+}
+
+/// applies the peephole patterns to the generated assembly until none of them matches
+pub fn optimize(lines: Vec<String>) -> Vec<String> {
+    let parsed = lines.iter().map(|line| parse(line)).collect();
+    let mut optimizer = Optimizer { lines, parsed };
+    let mut changed = true;
+    // a change may let a pattern that starts before it match, so we go over the lines again until nothing changes
+    while changed {
+        changed = false;
+        let mut line_i = 0;
+        while line_i < optimizer.lines.len() {
+            if optimizer.optimize_at(line_i) {
+                changed = true;
+                continue;
+            }
+            line_i += 1;
+        }
+    }
+    optimizer.lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn optimize_str(program: &str) -> Vec<String> {
+        optimize(program.trim().lines().map(|line| line.trim().to_string()).collect())
+    }
+    #[test]
+    fn test_push_pop() {
+        assert_eq!(optimize_str("
+        PUSH R1
+        MOV R1 5
+        ; a comment
+        POP R2
+        TSTL R2 R1
+        "), vec!["MOV R2 R1", "MOV R1 5", "; a comment", "TSTL R2 R1"]);
+        // R2 is used in between
+        let program = "
+        PUSH R1
+        ADD R1 R2 1
+        POP R2
+        ";
+        assert_eq!(optimize_str(program).len(), 3);
+        assert_eq!(optimize_str("
+        PUSH R1
+        CALL f
+        POP R1
+        ").len(), 3);
+        assert_eq!(optimize_str("
+        PUSH R2
+        LEA R1 .LSTR_0
+        POP R2
+        "), vec!["LEA R1 .LSTR_0"]);
+    }
+    #[test]
+    fn test_flag_move() {
+        let program = "
+        TSTL R2 R1
+        MOV R1 ZR
+        TSTN R1 0
+        FJMP .LIF_0_ELSE
+        MOV R1 1
+        .LIF_0_ELSE:
+        ADD R1 BP -3
+        ";
+        assert_eq!(optimize_str(program), vec!["TSTL R2 R1", "FJMP .LIF_0_ELSE", "MOV R1 1", ".LIF_0_ELSE:", "ADD R1 BP -3"]);
+        // R1 is read at the jump's target
+        let program = "
+        TSTL R2 R1
+        MOV R1 ZR
+        TSTN R1 0
+        FJMP .LIF_0_ELSE
+        MOV R1 1
+        .LIF_0_ELSE:
+        STR R2 R1
+        ";
+        assert_eq!(optimize_str(program).len(), 7);
+    }
+    #[test]
+    fn test_pops_moves_and_jumps() {
+        assert_eq!(optimize_str("
+        JUMP .LEND
+        ; return
+        .LEND:
+        POP ZR
+        POP ZR
+        POP ZR
+        MOV R2 R2
+        POP R1
+        RET
+        "), vec!["; return", ".LEND:", "ADD SP SP 3", "POP R1", "RET"]);
+        // the jump's target isn't the next line
+        assert_eq!(optimize_str("
+        JUMP .L1
+        .L2:
+        POP ZR
+        .L1:
+        RET
+        ").len(), 5);
+        // ZR is read after the chain
+        assert_eq!(optimize_str("
+        POP ZR
+        POP ZR
+        MOV R1 ZR
+        RET
+        ").len(), 4);
+        assert_eq!(optimize_str("
+        POP ZR
+        POP ZR
+        TJMP .L1
+        .L1:
+        RET
+        ").len(), 5);
+        assert_eq!(optimize_str("
+        POP ZR
+        POP ZR
+        TSTE R1 0
+        TJMP .L1
+        .L1:
+        RET
+        "), vec!["ADD SP SP 2", "TSTE R1 0", "TJMP .L1", ".L1:", "RET"]);
+    }
+}
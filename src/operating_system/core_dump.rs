@@ -0,0 +1,109 @@
+use std::io::stdin;
+use std::ops::Range;
+use std::str::FromStr;
+
+use super::backtrace::{backtrace, format_backtrace, Frame};
+use super::process::{function_at, Pid};
+use super::run::Location;
+use crate::cpu::instructions::Register;
+use crate::cpu::{Cpu, Fault, MemEntry};
+
+/*
+A core dump is the state of a process at the moment it faulted:
+its registers, the whole memory, the symbols of its executable at their loaded addresses & the output it wrote so far.
+The OS writes one when it's given a core directory, the post-mortem debugger reads it back.
+
+Post-mortem debugger commands:
+    info: the fault & where it happened
+    reg [register]: the value of the register, or of all of them
+    mem address|data_symbol [count]: count words of memory, 1 by default
+    bt: the backtrace of the faulting instruction
+    out: the output so far
+    quit
+*/
+
+pub struct CoreDump {
+    pub pid: Pid,
+    pub fault: Fault,
+    pub cpu: Cpu, // the registers of the faulting process & the memory of all processes
+    pub code: Range<u32>, // where the process's code is loaded
+    pub symbols: Vec<(u32, String)>, // code symbols at their loaded addresses, sorted
+    pub data_symbols: Vec<(u32, String)>, // data symbols at their loaded addresses, sorted
+    pub stdout: Vec<u8>,
+}
+
+impl CoreDump {
+    /// where the faulting instruction is
+    pub fn location(&self) -> Location {
+        let addr = self.cpu.regs.get(&Register::IR) as u32;
+        Location { pid: self.pid, addr, function: function_at(&self.symbols, &self.code, addr) }
+    }
+
+    pub fn backtrace(&self) -> Vec<Frame> {
+        backtrace(&self.cpu.regs, &self.cpu.mem, |addr| function_at(&self.symbols, &self.code, addr))
+    }
+
+    fn address_of(&self, arg: &str) -> Result<u32, String> {
+        if let Ok(addr) = arg.parse() {
+            return Ok(addr);
+        }
+        self.data_symbols.iter().find(|(_, name)| name == arg).map(|(addr, _)| *addr).ok_or(format!("unknown address: {}", arg))
+    }
+
+    fn describe_memory(&self, addr: u32) -> String {
+        let symbol = match self.data_symbols.iter().find(|(symbol_addr, _)| *symbol_addr == addr) {
+            Some((_, name)) => format!(" <{}>", name),
+            None => String::new(),
+        };
+        let value = match self.cpu.mem.try_get(addr) {
+            Ok(MemEntry::Num(num)) => num.to_string(),
+            Ok(MemEntry::Instruction(instr)) => instr.to_str(),
+            Err(_) => "??".to_string(),
+        };
+        format!("{}{}: {}", addr, symbol, value)
+    }
+
+    /// performs a command of the post-mortem debugger, returns its output or an error
+    pub fn command(&self, args: &[&str]) -> Result<String, String> {
+        match args {
+            ["info"] => Ok(format!("process {} faulted: {} at {}", self.pid, self.fault, self.location())),
+            ["reg"] => Ok(Register::ALL.iter().map(|reg| format!("{}: {}", reg, self.cpu.regs.get(reg))).collect::<Vec<String>>().join("\n")),
+            ["reg", reg] => {
+                let reg = Register::from_str(reg).map_err(|_| format!("unknown register: {}", reg))?;
+                Ok(self.cpu.regs.get(&reg).to_string())
+            }
+            ["mem", addr] | ["mem", addr, _] => {
+                let addr = self.address_of(addr)?;
+                let count: u32 = match args.get(2) {
+                    Some(count) => count.parse().map_err(|_| format!("invalid count: {}", count))?,
+                    None => 1,
+                };
+                let addrs: Vec<u32> = (0..count).map(|i| addr.checked_add(i)).collect::<Option<_>>().ok_or(format!("invalid count: {}", count))?;
+                Ok(addrs.into_iter().map(|addr| self.describe_memory(addr)).collect::<Vec<String>>().join("\n"))
+            }
+            ["bt"] => Ok(format_backtrace(&self.backtrace()).trim_end().to_string()),
+            ["out"] => Ok(String::from_utf8_lossy(&self.stdout).to_string()),
+            _ => Err(format!("unknown command: {}", args.join(" "))),
+        }
+    }
+}
+
+/// inspects the core dump with commands read from stdin, until quit or the end of the input
+pub fn debug_core(core: &CoreDump) {
+    println!("{}", core.command(&["info"]).unwrap());
+    loop {
+        let mut cmd = String::new();
+        if stdin().read_line(&mut cmd).unwrap_or(0) == 0 {
+            return;
+        }
+        let args: Vec<&str> = cmd.split_whitespace().collect();
+        match args.as_slice() {
+            [] => continue,
+            ["quit"] => return,
+            _ => match core.command(&args) {
+                Ok(output) => println!("{}", output),
+                Err(e) => println!("{}", e),
+            },
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use super::assembler::*;
+use crate::cpu::instructions::*;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+/*
+Link-time dead code elimination.
+The code of every object is split into chunks at its non .L labels (i.e at its functions), its data is split at every data label.
+A chunk is alive if it's reachable from the roots, which are the first chunk of the first object (where execution starts) & main.
+A chunk references the chunks that define the labels of its relocations (of its instructions or data words),
+& a code chunk references the chunk after it in its object if its last instruction may fall through to it.
+Unreachable chunks are dropped & the objects are compacted, the result is then linked as usual.
+*/
+
+/// a chunk is identified by its object, segment & index in the segment
+type ChunkId = (usize, Segment, usize);
+
+/// starts of the chunks of a segment, the first chunk always starts at 0
+fn chunk_starts<'a>(symbols: impl Iterator<Item = &'a Symbol>) -> Vec<u32> {
+    let mut starts: Vec<u32> = symbols.map(|symbol| symbol.addr).collect();
+    starts.push(0);
+    starts.sort();
+    starts.dedup();
+    starts
+}
+
+/// index of the chunk that contains addr
+fn chunk_of(starts: &[u32], addr: u32) -> usize {
+    starts.iter().rposition(|start| *start <= addr).unwrap()
+}
+
+fn chunk_end(starts: &[u32], chunk_i: usize, len: u32) -> u32 {
+    starts.get(chunk_i + 1).cloned().unwrap_or(len)
+}
+
+fn falls_through(instr: &Instruction) -> bool {
+    !matches!(instr, Instruction::Flow { op: FlowOp::JUMP, .. } | Instruction::Other { op: OtherOp::RET | OtherOp::HALT })
+}
+
+/// the object each exported label resolves to, a global definition takes precedence over weak ones
+/// also returns the global definitions of labels that are already defined as global, by object
+fn exported(objects: &[ObjectFile], symbols_of: fn(&ObjectFile) -> &HashMap<String, Symbol>) -> (HashMap<&str, usize>, Vec<(usize, &str)>) {
+    let mut exported: HashMap<&str, usize> = HashMap::new();
+    let mut conflicts = Vec::new();
+    for (object_i, object) in objects.iter().enumerate() {
+        for (label, symbol) in symbols_of(object).iter() {
+            let overrides = match exported.get(label.as_str()) {
+                Some(defined_i) => {
+                    let defined = &symbols_of(&objects[*defined_i])[label];
+                    if symbol.binding == Binding::Global && defined.binding == Binding::Global {
+                        conflicts.push((object_i, label.as_str()));
+                    }
+                    symbol.binding == Binding::Global && defined.binding == Binding::Weak
+                }
+                None => symbol.binding != Binding::Local,
+            };
+            if overrides {
+                exported.insert(label, object_i);
+            }
+        }
+    }
+    (exported, conflicts)
+}
+
+struct Chunks<'a> {
+    objects: &'a [ObjectFile],
+    code_starts: Vec<Vec<u32>>,
+    data_starts: Vec<Vec<u32>>,
+    exported_code: HashMap<&'a str, usize>, // label -> object that exports it
+    exported_data: HashMap<&'a str, usize>,
+    conflicts: Vec<ChunkId>, // conflicting definitions are kept so that the linker reports them
+}
+
+impl<'a> Chunks<'a> {
+    fn new(objects: &'a [ObjectFile]) -> Chunks<'a> {
+        let mut chunks = Chunks {
+            objects,
+            code_starts: Vec::new(),
+            data_starts: Vec::new(),
+            exported_code: HashMap::new(),
+            exported_data: HashMap::new(),
+            conflicts: Vec::new(),
+        };
+        for object in objects.iter() {
+            let funcs = object.code_symbols.iter().filter(|(label, _)| !is_temporary_label(label));
+            chunks.code_starts.push(chunk_starts(funcs.map(|(_, symbol)| symbol)));
+            chunks.data_starts.push(chunk_starts(object.data_symbols.values()));
+        }
+        let (exported_code, code_conflicts) = exported(objects, |object| &object.code_symbols);
+        let (exported_data, data_conflicts) = exported(objects, |object| &object.data_symbols);
+        chunks.conflicts = code_conflicts.iter()
+            .filter_map(|(object_i, label)| chunks.chunk_in(*object_i, Segment::Code, label))
+            .chain(data_conflicts.iter().filter_map(|(object_i, label)| chunks.chunk_in(*object_i, Segment::Data, label)))
+            .collect();
+        chunks.exported_code = exported_code;
+        chunks.exported_data = exported_data;
+        chunks
+    }
+
+    fn starts(&self, object_i: usize, segment: Segment) -> &Vec<u32> {
+        match segment {
+            Segment::Code => &self.code_starts[object_i],
+            Segment::Data => &self.data_starts[object_i],
+        }
+    }
+
+    fn chunk_in(&self, object_i: usize, segment: Segment, label: &str) -> Option<ChunkId> {
+        let symbols = match segment {
+            Segment::Code => &self.objects[object_i].code_symbols,
+            Segment::Data => &self.objects[object_i].data_symbols,
+        };
+        let symbol = symbols.get(label)?;
+        Some((object_i, segment, chunk_of(self.starts(object_i, segment), symbol.addr)))
+    }
+
+    /// the chunk that defines a label referenced by a relocation of object_i, following the linker's lookup rules
+    fn find(&self, object_i: usize, kind: &RelocationKind, label: &str) -> Option<ChunkId> {
+        let object = &self.objects[object_i];
+        let is_local = |symbol: Option<&Symbol>| symbol.is_some_and(|symbol| symbol.binding == Binding::Local);
+        let code = if is_local(object.code_symbols.get(label)) {
+            self.chunk_in(object_i, Segment::Code, label)
+        } else {
+            self.exported_code.get(label).and_then(|object_i| self.chunk_in(*object_i, Segment::Code, label))
+        };
+        if *kind == RelocationKind::CodeOffset {
+            return code;
+        }
+        let data = if is_local(object.data_symbols.get(label)) {
+            self.chunk_in(object_i, Segment::Data, label)
+        } else {
+            self.exported_data.get(label).and_then(|object_i| self.chunk_in(*object_i, Segment::Data, label))
+        };
+        data.or(code)
+    }
+
+    /// the chunks referenced by a chunk
+    fn references(&self, (object_i, segment, chunk_i): ChunkId) -> Vec<ChunkId> {
+        let mut refs = Vec::new();
+        let object = &self.objects[object_i];
+        let starts = self.starts(object_i, segment);
+        let len = match segment {
+            Segment::Code => object.code.len(),
+            Segment::Data => object.data.len(),
+        };
+        let (start, end) = (starts[chunk_i], chunk_end(starts, chunk_i, len as u32));
+        for reloc in object.relocations.iter().filter(|reloc| reloc.segment == segment && reloc.offset >= start && reloc.offset < end) {
+            for label in reloc.expr.labels() {
+                refs.extend(self.find(object_i, &reloc.kind, label));
+            }
+        }
+        if segment == Segment::Code && chunk_i + 1 < starts.len() && (start == end || falls_through(&object.code[end as usize - 1])) {
+            refs.push((object_i, Segment::Code, chunk_i + 1));
+        }
+        refs
+    }
+
+    fn alive(&self) -> HashSet<ChunkId> {
+        let mut alive = HashSet::new();
+        let mut queue = VecDeque::new();
+        if !self.objects.is_empty() {
+            queue.push_back((0, Segment::Code, 0));
+        }
+        queue.extend(self.find(0, &RelocationKind::CodeOffset, "main").filter(|_| !self.objects.is_empty()));
+        queue.extend(self.conflicts.iter().cloned());
+        while let Some(chunk) = queue.pop_front() {
+            if alive.insert(chunk) {
+                queue.extend(self.references(chunk));
+            }
+        }
+        alive
+    }
+}
+
+/// maps the addresses of a segment to their addresses after dropping dead chunks
+/// align is kept for data, each chunk stays at the same offset modulo align
+struct Compaction {
+    starts: Vec<u32>,
+    new_starts: Vec<Option<u32>>, // None for dead chunks
+    new_len: u32,
+}
+
+impl Compaction {
+    fn new(starts: &[u32], len: u32, is_alive: impl Fn(usize) -> bool, align: u32) -> Compaction {
+        let mut new_starts = Vec::new();
+        let mut new_len = 0;
+        for (chunk_i, start) in starts.iter().enumerate() {
+            if !is_alive(chunk_i) {
+                new_starts.push(None);
+                continue;
+            }
+            new_len += (start % align + align - new_len % align) % align;
+            new_starts.push(Some(new_len));
+            new_len += chunk_end(starts, chunk_i, len) - start;
+        }
+        Compaction { starts: starts.to_vec(), new_starts, new_len }
+    }
+
+    /// new address of addr, None if it's in a dead chunk
+    fn map(&self, addr: u32) -> Option<u32> {
+        let chunk_i = chunk_of(&self.starts, addr);
+        self.new_starts[chunk_i].map(|new_start| new_start + addr - self.starts[chunk_i])
+    }
+
+    /// new address of addr, or of the first live address after it
+    fn map_or_next(&self, addr: u32) -> u32 {
+        let chunk_i = chunk_of(&self.starts, addr);
+        if let Some(new_addr) = self.map(addr) {
+            return new_addr;
+        }
+        self.new_starts[chunk_i..].iter().flatten().next().cloned().unwrap_or(self.new_len)
+    }
+
+    fn copy<T: Clone>(&self, items: &[T], padding: T) -> Vec<T> {
+        let mut res = Vec::new();
+        for (chunk_i, new_start) in self.new_starts.iter().enumerate() {
+            if let Some(new_start) = new_start {
+                res.resize(*new_start as usize, padding.clone());
+                let end = chunk_end(&self.starts, chunk_i, items.len() as u32);
+                res.extend(items[self.starts[chunk_i] as usize..end as usize].iter().cloned());
+            }
+        }
+        res
+    }
+}
+
+fn compact_symbols(symbols: &HashMap<String, Symbol>, compaction: &Compaction) -> HashMap<String, Symbol> {
+    symbols.iter()
+        .filter_map(|(label, symbol)| compaction.map(symbol.addr).map(|addr| (label.clone(), Symbol { addr, ..symbol.clone() })))
+        .collect()
+}
+
+/// returns the objects without the code & data that can't be reached from the entry point or main
+pub fn eliminate_dead_code(objects: &[ObjectFile]) -> Vec<ObjectFile> {
+    let chunks = Chunks::new(objects);
+    let alive = chunks.alive();
+    let mut res = Vec::new();
+    for (object_i, object) in objects.iter().enumerate() {
+        let code = Compaction::new(&chunks.code_starts[object_i], object.code.len() as u32,
+            |chunk_i| alive.contains(&(object_i, Segment::Code, chunk_i)), 1);
+        let data = Compaction::new(&chunks.data_starts[object_i], object.data.len() as u32,
+            |chunk_i| alive.contains(&(object_i, Segment::Data, chunk_i)), object.data_align);
+        res.push(ObjectFile {
+            name: object.name.clone(),
+            code: code.copy(&object.code, Instruction::Other { op: OtherOp::HALT }),
+            data: data.copy(&object.data, 0),
+            data_align: object.data_align,
+            code_symbols: compact_symbols(&object.code_symbols, &code),
+            data_symbols: compact_symbols(&object.data_symbols, &data),
+            relocations: object.relocations.iter()
+                .filter_map(|reloc| {
+                    let compaction = if reloc.segment == Segment::Code { &code } else { &data };
+                    compaction.map(reloc.offset).map(|offset| Relocation { offset, ..reloc.clone() })
+                })
+                .collect(),
+            line_addrs: object.line_addrs.iter().map(|addr| code.map_or_next(*addr)).collect(),
+        });
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operating_system::linker::link;
+    #[test]
+    fn test_eliminate_dead_code() {
+        let main = assemble_object("
+        .stringz unused_str x
+        .stringz s hi
+        JUMP main
+        main:
+        LEA R1 s
+        CALL F
+        HALT
+        unused:
+        CALL G
+        RET
+        ", "main").unwrap();
+        let lib = assemble_object("
+        .word .Ltable 1 .Lname
+        .stringz .Lunused x
+        .stringz .Lname ab
+        F:
+        LEA R1 .Ltable
+        G:
+        RET
+        H:
+        RET
+        ", "lib").unwrap();
+        let objects = eliminate_dead_code(&[main, lib]);
+        // unused & H are dropped, G is kept since F falls through to it
+        assert_eq!(objects[0].code.len(), 4);
+        assert!(!objects[0].code_symbols.contains_key("unused"));
+        assert_eq!(objects[0].data, vec!['h' as i32, 'i' as i32, 0]);
+        // .Lname is kept since .Ltable references it
+        assert_eq!(objects[1].data, vec![1, 0, 'a' as i32, 'b' as i32, 0]);
+        assert_eq!(objects[1].relocations[0].offset, 1);
+        assert_eq!(objects[1].code.len(), 2);
+        assert!(objects[1].code_symbols.contains_key("G"));
+        assert!(!objects[1].code_symbols.contains_key("H"));
+        let exec = link(&objects).unwrap();
+        assert_eq!(*exec.symbol_table.get("F").unwrap(), 4);
+        assert_eq!(*exec.data_table.get("s").unwrap(), 0);
+        // a line of dropped code maps to the next address that's kept
+        assert_eq!(*exec.symbol_table.get("_LINE_8").unwrap(), 4);
+    }
+    #[test]
+    fn test_conflicts_are_kept() {
+        let main = assemble_object("
+        CALL F
+        HALT
+        F:
+        RET
+        ", "main").unwrap();
+        let lib = assemble_object("
+        F:
+        RET
+        ", "lib").unwrap();
+        let errors = link(&eliminate_dead_code(&[main, lib])).err().unwrap();
+        assert!(matches!(&errors[0].cause, AsmErrorCause::MultipleDefinition { label, .. } if label == "F"));
+    }
+}
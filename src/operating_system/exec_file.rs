@@ -0,0 +1,598 @@
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+use self::serde_json::Value as JsonValue;
+use super::assembler::{Binding, Executable, Expr, LoadRelocation, ObjectFile, Relocation, RelocationKind, Segment, SourceLoc, Symbol};
+use super::core_dump::CoreDump;
+use super::linker::{immediate, Archive};
+use crate::cpu::instructions::{Instruction, Register};
+use crate::cpu::{Cpu, Fault, MemEntry};
+
+/*
+All files are JSON objects with a "magic" field that identifies the kind of file,
+and a "version" field, readers refuse versions they don't know.
+
+Executable file format:
+    - entry_point: relative address of the first instruction to execute
+    - code: list of instructions in assembly syntax, with all labels already resolved
+    - data: list of data words
+    - symbol_table: label -> relative code address, a local function is under its label, or label@object if the label is taken
+    - data_table: label -> relative data address
+    - relocations: list of {offset, segment ("code" / "data"), target ("code" / "data"), count},
+      the instructions whose immediate & the data words that hold absolute addresses,
+      resolved for the default code & data addresses, see LoadRelocation
+
+Object file format:
+    - name: name of the object, used in diagnostics
+    - code: list of instructions in assembly syntax, label operands are 0 until relocated
+    - data: list of data words
+    - data_align: the absolute address of the data must be a multiple of this
+    - code_symbols, data_symbols: label -> {addr, size, line, column, binding ("global" / "local" / "weak")}
+    - relocations: list of {offset, segment ("code" / "data"), kind ("code_offset" / "immediate"), expr (operand expression in assembly syntax), line, column}
+    - undefined: symbols referenced but not defined by the object (informational, derived from relocations)
+    - line_addrs: relative code address of each source line
+
+Archive file format:
+    - members: list of objects, in the object file format (without magic & version)
+
+Core file format:
+    - pid: the process that faulted
+    - fault: {kind ("invalid_access" / "not_numeric" / "not_executable" / "division_by_zero"), addr (absent for division_by_zero)}
+    - registers: register -> value
+    - memory: list of [address, value] of every written address, the value is a number or an instruction in assembly syntax
+    - code: [start, end) of the process's code
+    - symbol_table, data_table: label -> absolute address
+    - stdout: list of the bytes written to stdout
+*/
+
+pub const EXEC_MAGIC: &str = "c_to_vm executable";
+pub const OBJECT_MAGIC: &str = "c_to_vm object";
+pub const ARCHIVE_MAGIC: &str = "c_to_vm archive";
+pub const CORE_MAGIC: &str = "c_to_vm core";
+pub const EXEC_FORMAT_VERSION: u64 = 3;
+
+#[derive(Debug)]
+pub enum ExecFileError {
+    Io(std::io::Error),
+    Malformed(String),
+    UnsupportedVersion(u64),
+}
+
+impl std::fmt::Display for ExecFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExecFileError::Io(e) => write!(f, "io error: {}", e),
+            ExecFileError::Malformed(reason) => write!(f, "malformed executable: {}", reason),
+            ExecFileError::UnsupportedVersion(v) => {
+                write!(f, "unsupported executable version {} (expected {})", v, EXEC_FORMAT_VERSION)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for ExecFileError {
+    fn from(e: std::io::Error) -> ExecFileError {
+        ExecFileError::Io(e)
+    }
+}
+
+fn table_to_json(table: &HashMap<String, u32>) -> JsonValue {
+    let mut map = serde_json::Map::new();
+    for (label, addr) in table.iter() {
+        map.insert(label.clone(), JsonValue::from(*addr));
+    }
+    JsonValue::Object(map)
+}
+
+fn table_from_json(node: &JsonValue, field: &str) -> Result<HashMap<String, u32>, ExecFileError> {
+    let map = node[field].as_object().ok_or_else(|| ExecFileError::Malformed(format!("missing {}", field)))?;
+    let mut table = HashMap::new();
+    for (label, addr) in map.iter() {
+        let addr = addr.as_u64().ok_or_else(|| ExecFileError::Malformed(format!("bad address for {} in {}", label, field)))?;
+        table.insert(label.clone(), addr as u32);
+    }
+    Ok(table)
+}
+
+pub fn executable_to_json(exec: &Executable) -> JsonValue {
+    serde_json::json!({
+        "magic": EXEC_MAGIC,
+        "version": EXEC_FORMAT_VERSION,
+        "entry_point": exec.entry_point,
+        "code": exec.code.iter().map(|instr| instr.to_str()).collect::<Vec<String>>(),
+        "data": exec.data,
+        "symbol_table": table_to_json(&exec.symbol_table),
+        "data_table": table_to_json(&exec.data_table),
+        "relocations": exec.relocations.iter().map(|reloc| serde_json::json!({
+            "offset": reloc.offset,
+            "segment": segment_name(reloc.segment),
+            "target": segment_name(reloc.target),
+            "count": reloc.count,
+        })).collect::<Vec<JsonValue>>(),
+    })
+}
+
+fn segment_name(segment: Segment) -> &'static str {
+    match segment {
+        Segment::Code => "code",
+        Segment::Data => "data",
+    }
+}
+
+fn segment_from_json(node: &JsonValue, field: &str) -> Result<Segment, ExecFileError> {
+    match node[field].as_str() {
+        Some("code") => Ok(Segment::Code),
+        Some("data") => Ok(Segment::Data),
+        _ => Err(ExecFileError::Malformed(format!("bad relocation {}", field))),
+    }
+}
+
+fn load_relocation_from_json(node: &JsonValue) -> Result<LoadRelocation, ExecFileError> {
+    Ok(LoadRelocation {
+        offset: u64_field(node, "offset")? as u32,
+        segment: segment_from_json(node, "segment")?,
+        target: segment_from_json(node, "target")?,
+        count: node["count"].as_i64().ok_or_else(|| ExecFileError::Malformed("missing count".to_string()))? as i32,
+    })
+}
+
+fn check_header(node: &JsonValue, magic: &str) -> Result<(), ExecFileError> {
+    if node["magic"].as_str() != Some(magic) {
+        return Err(ExecFileError::Malformed(format!("not a {} file", magic)));
+    }
+    let version = node["version"].as_u64().ok_or_else(|| ExecFileError::Malformed("missing version".to_string()))?;
+    if version != EXEC_FORMAT_VERSION {
+        return Err(ExecFileError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+fn u64_field(node: &JsonValue, field: &str) -> Result<u64, ExecFileError> {
+    node[field].as_u64().ok_or_else(|| ExecFileError::Malformed(format!("missing {}", field)))
+}
+
+fn code_from_json(node: &JsonValue) -> Result<Vec<Instruction>, ExecFileError> {
+    let mut code = Vec::new();
+    for (instr_i, instr) in node["code"].as_array().ok_or_else(|| ExecFileError::Malformed("missing code".to_string()))?.iter().enumerate() {
+        let instr = instr.as_str()
+            .and_then(|s| Instruction::from_str(s).ok())
+            .ok_or_else(|| ExecFileError::Malformed(format!("bad instruction at {}", instr_i)))?;
+        code.push(instr);
+    }
+    Ok(code)
+}
+
+fn data_from_json(node: &JsonValue) -> Result<Vec<i32>, ExecFileError> {
+    let mut data = Vec::new();
+    for (data_i, val) in node["data"].as_array().ok_or_else(|| ExecFileError::Malformed("missing data".to_string()))?.iter().enumerate() {
+        let val = val.as_i64().ok_or_else(|| ExecFileError::Malformed(format!("bad data word at {}", data_i)))?;
+        data.push(val as i32);
+    }
+    Ok(data)
+}
+
+pub fn executable_from_json(node: &JsonValue) -> Result<Executable, ExecFileError> {
+    check_header(node, EXEC_MAGIC)?;
+    let code = code_from_json(node)?;
+    let data = data_from_json(node)?;
+    let mut relocations = Vec::new();
+    for reloc in node["relocations"].as_array().ok_or_else(|| ExecFileError::Malformed("missing relocations".to_string()))?.iter() {
+        let reloc = load_relocation_from_json(reloc)?;
+        check_load_relocation(&reloc, &code, &data)?;
+        relocations.push(reloc);
+    }
+    let entry_point = u64_field(node, "entry_point")?;
+    if entry_point >= code.len() as u64 {
+        return Err(ExecFileError::Malformed(format!("entry point {} is outside of the code", entry_point)));
+    }
+    Ok(Executable {
+        code,
+        data,
+        symbol_table: table_from_json(node, "symbol_table")?,
+        data_table: table_from_json(node, "data_table")?,
+        entry_point: entry_point as u32,
+        relocations,
+    })
+}
+
+/// a load relocation must patch an instruction with an immediate operand or a data word of the executable
+fn check_load_relocation(reloc: &LoadRelocation, code: &[Instruction], data: &[i32]) -> Result<(), ExecFileError> {
+    let valid = match reloc.segment {
+        Segment::Code => code.get(reloc.offset as usize).and_then(immediate).is_some(),
+        Segment::Data => (reloc.offset as usize) < data.len(),
+    };
+    if !valid {
+        return Err(ExecFileError::Malformed(format!("bad load relocation at {}", reloc.offset)));
+    }
+    Ok(())
+}
+
+fn symbols_to_json(symbols: &HashMap<String, Symbol>) -> JsonValue {
+    let mut map = serde_json::Map::new();
+    for (label, symbol) in symbols.iter() {
+        map.insert(label.clone(), serde_json::json!({
+            "addr": symbol.addr,
+            "size": symbol.size,
+            "line": symbol.loc.line,
+            "column": symbol.loc.column,
+            "binding": binding_name(&symbol.binding),
+        }));
+    }
+    JsonValue::Object(map)
+}
+
+fn loc_from_json(node: &JsonValue) -> Result<SourceLoc, ExecFileError> {
+    Ok(SourceLoc {
+        line: u64_field(node, "line")? as usize,
+        column: u64_field(node, "column")? as usize,
+    })
+}
+
+fn symbols_from_json(node: &JsonValue, field: &str) -> Result<HashMap<String, Symbol>, ExecFileError> {
+    let map = node[field].as_object().ok_or_else(|| ExecFileError::Malformed(format!("missing {}", field)))?;
+    let mut symbols = HashMap::new();
+    for (label, symbol) in map.iter() {
+        symbols.insert(label.clone(), Symbol {
+            addr: u64_field(symbol, "addr")? as u32,
+            size: u64_field(symbol, "size")? as u32,
+            loc: loc_from_json(symbol)?,
+            binding: binding_from_json(symbol)?,
+        });
+    }
+    Ok(symbols)
+}
+
+fn binding_name(binding: &Binding) -> &'static str {
+    match binding {
+        Binding::Global => "global",
+        Binding::Local => "local",
+        Binding::Weak => "weak",
+    }
+}
+
+fn binding_from_json(node: &JsonValue) -> Result<Binding, ExecFileError> {
+    match node["binding"].as_str() {
+        Some("global") => Ok(Binding::Global),
+        Some("local") => Ok(Binding::Local),
+        Some("weak") => Ok(Binding::Weak),
+        _ => Err(ExecFileError::Malformed("bad symbol binding".to_string())),
+    }
+}
+
+fn relocation_kind_name(kind: &RelocationKind) -> &'static str {
+    match kind {
+        RelocationKind::CodeOffset => "code_offset",
+        RelocationKind::Immediate => "immediate",
+    }
+}
+
+fn relocation_from_json(node: &JsonValue) -> Result<Relocation, ExecFileError> {
+    let kind = match node["kind"].as_str() {
+        Some("code_offset") => RelocationKind::CodeOffset,
+        Some("immediate") => RelocationKind::Immediate,
+        _ => return Err(ExecFileError::Malformed("bad relocation kind".to_string())),
+    };
+    Ok(Relocation {
+        offset: u64_field(node, "offset")? as u32,
+        segment: segment_from_json(node, "segment")?,
+        kind,
+        expr: node["expr"].as_str()
+            .and_then(Expr::parse)
+            .ok_or_else(|| ExecFileError::Malformed("bad relocation expression".to_string()))?,
+        loc: loc_from_json(node)?,
+    })
+}
+
+fn object_body_to_json(object: &ObjectFile) -> JsonValue {
+    serde_json::json!({
+        "name": object.name,
+        "code": object.code.iter().map(|instr| instr.to_str()).collect::<Vec<String>>(),
+        "data": object.data,
+        "data_align": object.data_align,
+        "code_symbols": symbols_to_json(&object.code_symbols),
+        "data_symbols": symbols_to_json(&object.data_symbols),
+        "relocations": object.relocations.iter().map(|reloc| serde_json::json!({
+            "offset": reloc.offset,
+            "segment": segment_name(reloc.segment),
+            "kind": relocation_kind_name(&reloc.kind),
+            "expr": reloc.expr.to_str(),
+            "line": reloc.loc.line,
+            "column": reloc.loc.column,
+        })).collect::<Vec<JsonValue>>(),
+        "undefined": object.undefined_symbols(),
+        "line_addrs": object.line_addrs,
+    })
+}
+
+fn object_body_from_json(node: &JsonValue) -> Result<ObjectFile, ExecFileError> {
+    let mut relocations = Vec::new();
+    for reloc in node["relocations"].as_array().ok_or_else(|| ExecFileError::Malformed("missing relocations".to_string()))?.iter() {
+        relocations.push(relocation_from_json(reloc)?);
+    }
+    let mut line_addrs = Vec::new();
+    for addr in node["line_addrs"].as_array().ok_or_else(|| ExecFileError::Malformed("missing line_addrs".to_string()))?.iter() {
+        line_addrs.push(addr.as_u64().ok_or_else(|| ExecFileError::Malformed("bad line address".to_string()))? as u32);
+    }
+    Ok(ObjectFile {
+        name: node["name"].as_str().ok_or_else(|| ExecFileError::Malformed("missing name".to_string()))?.to_string(),
+        code: code_from_json(node)?,
+        data: data_from_json(node)?,
+        data_align: u64_field(node, "data_align")? as u32,
+        code_symbols: symbols_from_json(node, "code_symbols")?,
+        data_symbols: symbols_from_json(node, "data_symbols")?,
+        relocations,
+        line_addrs,
+    })
+}
+
+/// adds the magic & version fields to a file's JSON object
+fn with_header(mut node: JsonValue, magic: &str) -> JsonValue {
+    node["magic"] = JsonValue::from(magic);
+    node["version"] = JsonValue::from(EXEC_FORMAT_VERSION);
+    node
+}
+
+pub fn object_to_json(object: &ObjectFile) -> JsonValue {
+    with_header(object_body_to_json(object), OBJECT_MAGIC)
+}
+
+pub fn object_from_json(node: &JsonValue) -> Result<ObjectFile, ExecFileError> {
+    check_header(node, OBJECT_MAGIC)?;
+    object_body_from_json(node)
+}
+
+pub fn archive_to_json(archive: &Archive) -> JsonValue {
+    with_header(serde_json::json!({
+        "members": archive.members.iter().map(object_body_to_json).collect::<Vec<JsonValue>>(),
+    }), ARCHIVE_MAGIC)
+}
+
+pub fn archive_from_json(node: &JsonValue) -> Result<Archive, ExecFileError> {
+    check_header(node, ARCHIVE_MAGIC)?;
+    let mut members = Vec::new();
+    for member in node["members"].as_array().ok_or_else(|| ExecFileError::Malformed("missing members".to_string()))?.iter() {
+        members.push(object_body_from_json(member)?);
+    }
+    Ok(Archive { members })
+}
+
+fn fault_to_json(fault: &Fault) -> JsonValue {
+    match fault {
+        Fault::InvalidAccess(addr) => serde_json::json!({"kind": "invalid_access", "addr": addr}),
+        Fault::NotNumeric(addr) => serde_json::json!({"kind": "not_numeric", "addr": addr}),
+        Fault::NotExecutable(addr) => serde_json::json!({"kind": "not_executable", "addr": addr}),
+        Fault::DivisionByZero => serde_json::json!({"kind": "division_by_zero"}),
+    }
+}
+
+fn fault_from_json(node: &JsonValue) -> Result<Fault, ExecFileError> {
+    match node["kind"].as_str() {
+        Some("invalid_access") => Ok(Fault::InvalidAccess(u64_field(node, "addr")? as u32)),
+        Some("not_numeric") => Ok(Fault::NotNumeric(u64_field(node, "addr")? as u32)),
+        Some("not_executable") => Ok(Fault::NotExecutable(u64_field(node, "addr")? as u32)),
+        Some("division_by_zero") => Ok(Fault::DivisionByZero),
+        _ => Err(ExecFileError::Malformed("bad fault kind".to_string())),
+    }
+}
+
+fn symbol_list_to_json(symbols: &[(u32, String)]) -> JsonValue {
+    table_to_json(&symbols.iter().map(|(addr, name)| (name.clone(), *addr)).collect())
+}
+
+fn symbol_list_from_json(node: &JsonValue, field: &str) -> Result<Vec<(u32, String)>, ExecFileError> {
+    let mut symbols: Vec<(u32, String)> = table_from_json(node, field)?.into_iter().map(|(name, addr)| (addr, name)).collect();
+    symbols.sort();
+    Ok(symbols)
+}
+
+pub fn core_to_json(core: &CoreDump) -> JsonValue {
+    let mut registers = serde_json::Map::new();
+    for reg in Register::ALL.iter() {
+        registers.insert(reg.to_str(), JsonValue::from(core.cpu.regs.get(reg)));
+    }
+    with_header(serde_json::json!({
+        "pid": core.pid,
+        "fault": fault_to_json(&core.fault),
+        "registers": registers,
+        "memory": core.cpu.mem.entries().iter().map(|(addr, entry)| match entry {
+            MemEntry::Num(num) => serde_json::json!([addr, num]),
+            MemEntry::Instruction(instr) => serde_json::json!([addr, instr.to_str()]),
+        }).collect::<Vec<JsonValue>>(),
+        "code": [core.code.start, core.code.end],
+        "symbol_table": symbol_list_to_json(&core.symbols),
+        "data_table": symbol_list_to_json(&core.data_symbols),
+        "stdout": core.stdout,
+    }), CORE_MAGIC)
+}
+
+pub fn core_from_json(node: &JsonValue) -> Result<CoreDump, ExecFileError> {
+    check_header(node, CORE_MAGIC)?;
+    let mut cpu = Cpu::new();
+    for reg in Register::ALL.iter() {
+        let value = node["registers"][reg.to_str()].as_i64().ok_or_else(|| ExecFileError::Malformed(format!("missing register {}", reg)))?;
+        cpu.regs.set(reg, value as i32);
+    }
+    for entry in node["memory"].as_array().ok_or_else(|| ExecFileError::Malformed("missing memory".to_string()))?.iter() {
+        let addr = entry[0].as_u64().ok_or_else(|| ExecFileError::Malformed("bad memory address".to_string()))? as u32;
+        let value = match (entry[1].as_i64(), entry[1].as_str()) {
+            (Some(num), _) => MemEntry::Num(num as i32),
+            (_, Some(instr)) => MemEntry::Instruction(Instruction::from_str(instr)
+                .map_err(|_| ExecFileError::Malformed(format!("bad instruction at {}", addr)))?),
+            _ => return Err(ExecFileError::Malformed(format!("bad memory value at {}", addr))),
+        };
+        cpu.mem.set(addr, value);
+    }
+    let code = match (node["code"][0].as_u64(), node["code"][1].as_u64()) {
+        (Some(start), Some(end)) => start as u32..end as u32,
+        _ => return Err(ExecFileError::Malformed("missing code".to_string())),
+    };
+    let mut stdout = Vec::new();
+    for byte in node["stdout"].as_array().ok_or_else(|| ExecFileError::Malformed("missing stdout".to_string()))?.iter() {
+        stdout.push(byte.as_u64().ok_or_else(|| ExecFileError::Malformed("bad stdout byte".to_string()))? as u8);
+    }
+    Ok(CoreDump {
+        pid: u64_field(node, "pid")? as u32,
+        fault: fault_from_json(&node["fault"])?,
+        cpu,
+        code,
+        symbols: symbol_list_from_json(node, "symbol_table")?,
+        data_symbols: symbol_list_from_json(node, "data_table")?,
+        stdout,
+    })
+}
+
+fn write_json(node: &JsonValue, path: &str) -> Result<(), ExecFileError> {
+    let mut file = File::create(path)?;
+    write!(file, "{}", node)?;
+    Ok(())
+}
+
+fn read_json(path: &str) -> Result<JsonValue, ExecFileError> {
+    let mut file = File::open(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    serde_json::from_str(&content).map_err(|e| ExecFileError::Malformed(e.to_string()))
+}
+
+pub fn save_executable(exec: &Executable, path: &str) -> Result<(), ExecFileError> {
+    write_json(&executable_to_json(exec), path)
+}
+
+pub fn load_executable(path: &str) -> Result<Executable, ExecFileError> {
+    executable_from_json(&read_json(path)?)
+}
+
+pub fn save_object(object: &ObjectFile, path: &str) -> Result<(), ExecFileError> {
+    write_json(&object_to_json(object), path)
+}
+
+pub fn load_object(path: &str) -> Result<ObjectFile, ExecFileError> {
+    object_from_json(&read_json(path)?)
+}
+
+pub fn save_archive(archive: &Archive, path: &str) -> Result<(), ExecFileError> {
+    write_json(&archive_to_json(archive), path)
+}
+
+pub fn load_archive(path: &str) -> Result<Archive, ExecFileError> {
+    archive_from_json(&read_json(path)?)
+}
+
+pub fn save_core(core: &CoreDump, path: &str) -> Result<(), ExecFileError> {
+    write_json(&core_to_json(core), path)
+}
+
+pub fn load_core(path: &str) -> Result<CoreDump, ExecFileError> {
+    core_from_json(&read_json(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::assembler::{assemble, assemble_object};
+    #[test]
+    fn test_round_trip() {
+        let program = "
+        .stringz s1 hi
+        .word p s1 MAIN
+        MAIN:
+        LEA R1 s1
+        MOV R2 -1
+        CALL F
+        HALT
+        F:
+        RET
+        ";
+        let exec = assemble(program).unwrap();
+        let loaded = executable_from_json(&executable_to_json(&exec)).unwrap();
+        assert_eq!(loaded.code, exec.code);
+        assert_eq!(loaded.data, exec.data);
+        assert_eq!(loaded.symbol_table, exec.symbol_table);
+        assert_eq!(loaded.data_table, exec.data_table);
+        assert_eq!(loaded.entry_point, exec.entry_point);
+        assert_eq!(loaded.relocations, exec.relocations);
+    }
+    #[test]
+    fn test_object_round_trip() {
+        let program = "
+        .block b 2
+        .word p b+1 F
+        F:
+        LEA R1 b
+        CALL G
+        RET
+        ";
+        let object = assemble_object(program, "f").unwrap();
+        let archive = Archive { members: vec![object.clone()] };
+        let loaded = archive_from_json(&archive_to_json(&archive)).unwrap();
+        assert_eq!(loaded.members.len(), 1);
+        let loaded = &loaded.members[0];
+        assert_eq!(loaded.name, object.name);
+        assert_eq!(loaded.code, object.code);
+        assert_eq!(loaded.data, object.data);
+        assert_eq!(loaded.data_align, object.data_align);
+        assert_eq!(loaded.code_symbols, object.code_symbols);
+        assert_eq!(loaded.data_symbols, object.data_symbols);
+        assert_eq!(loaded.relocations, object.relocations);
+        assert_eq!(loaded.line_addrs, object.line_addrs);
+        assert!(object_from_json(&archive_to_json(&archive)).is_err());
+    }
+    #[test]
+    fn test_core_round_trip() {
+        let mut cpu = Cpu::new();
+        cpu.regs.set(&Register::IR, 1001);
+        cpu.regs.set(&Register::R2, -3);
+        cpu.mem.set(1000, MemEntry::Instruction(Instruction::from_str("MOV R1 -1").unwrap()));
+        cpu.mem.set(500, MemEntry::Num(-7));
+        let core = CoreDump {
+            pid: 2,
+            fault: Fault::InvalidAccess(40),
+            cpu,
+            code: 1000..1010,
+            symbols: vec![(1000, "main".to_string()), (1005, "f".to_string())],
+            data_symbols: vec![(500, "x".to_string())],
+            stdout: "é".as_bytes().to_vec(),
+        };
+        let loaded = core_from_json(&core_to_json(&core)).unwrap();
+        assert_eq!(loaded.pid, core.pid);
+        assert_eq!(loaded.fault, core.fault);
+        for reg in Register::ALL.iter() {
+            assert_eq!(loaded.cpu.regs.get(reg), core.cpu.regs.get(reg));
+        }
+        assert_eq!(loaded.cpu.mem.entries(), core.cpu.mem.entries());
+        assert_eq!(loaded.code, core.code);
+        assert_eq!(loaded.symbols, core.symbols);
+        assert_eq!(loaded.data_symbols, core.data_symbols);
+        assert_eq!(loaded.stdout, core.stdout);
+        assert!(executable_from_json(&core_to_json(&core)).is_err());
+    }
+    #[test]
+    fn test_malformed_executable() {
+        // relocations & the entry point must be inside of the code & data
+        let exec = assemble(".word p MAIN\nMAIN:\nLEA R1 p\nMOV R2 1\nHALT").unwrap();
+        let node = executable_to_json(&exec);
+        assert!(executable_from_json(&node).is_ok());
+        let malformed = |field: &str, value: JsonValue| {
+            let mut node = node.clone();
+            node[field] = value;
+            matches!(executable_from_json(&node), Err(ExecFileError::Malformed(_)))
+        };
+        assert!(malformed("entry_point", JsonValue::from(3)));
+        for (offset, segment) in [(3, "code"), (2, "code"), (1, "data")] {
+            let reloc = serde_json::json!([{ "offset": offset, "segment": segment, "target": "code", "count": 1 }]);
+            assert!(malformed("relocations", reloc), "{} {}", segment, offset);
+        }
+    }
+    #[test]
+    fn test_unsupported_version() {
+        let mut node = executable_to_json(&assemble("HALT").unwrap());
+        node["version"] = JsonValue::from(EXEC_FORMAT_VERSION + 1);
+        match executable_from_json(&node) {
+            Err(ExecFileError::UnsupportedVersion(v)) => assert_eq!(v, EXEC_FORMAT_VERSION + 1),
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/*
+The files that programs open are backed by a host directory, the root of the VM's filesystem.
+Paths are relative to the root, paths that lead outside of it are rejected:
+absolute paths, .. above the root, symlinks to outside of the root & dangling symlinks.
+*/
+
+// flags of open, as in POSIX
+pub const O_RDONLY: i32 = 0;
+pub const O_WRONLY: i32 = 1;
+pub const O_RDWR: i32 = 2;
+pub const O_CREAT: i32 = 64;
+pub const O_TRUNC: i32 = 512;
+pub const O_APPEND: i32 = 1024;
+
+// whence of lseek
+pub const SEEK_SET: i32 = 0;
+pub const SEEK_CUR: i32 = 1;
+pub const SEEK_END: i32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct FileSystem {
+    root: PathBuf,
+}
+
+fn escape_error(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is outside of the filesystem", path))
+}
+
+impl FileSystem {
+    pub fn new(root: impl Into<PathBuf>) -> FileSystem {
+        FileSystem { root: root.into() }
+    }
+
+    /// the host path of a path in the VM's filesystem
+    pub fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let mut relative = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !relative.pop() {
+                        return Err(escape_error(path));
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => return Err(escape_error(path)),
+            }
+        }
+        if relative.as_os_str().is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the root isn't a file"));
+        }
+        let host_path = self.root.join(&relative);
+        // a symlink in the path may lead outside of the root, a file that doesn't exist yet is created in its parent,
+        // a dangling symlink can't be checked, creating the file would create its target wherever it is
+        let existing = match fs::symlink_metadata(&host_path) {
+            Ok(metadata) if metadata.file_type().is_symlink() && !host_path.exists() => return Err(escape_error(path)),
+            Ok(_) => host_path.clone(),
+            Err(_) => host_path.parent().unwrap().to_path_buf(),
+        };
+        if !existing.canonicalize()?.starts_with(self.root.canonicalize()?) {
+            return Err(escape_error(path));
+        }
+        Ok(host_path)
+    }
+
+    pub fn open(&self, path: &str, flags: i32) -> io::Result<File> {
+        let mut options = OpenOptions::new();
+        match flags & 3 {
+            O_RDONLY => options.read(true),
+            O_WRONLY => options.write(true),
+            O_RDWR => options.read(true).write(true),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid access mode")),
+        };
+        options.create(flags & O_CREAT != 0).truncate(flags & O_TRUNC != 0).append(flags & O_APPEND != 0);
+        options.open(self.resolve(path)?)
+    }
+
+    pub fn unlink(&self, path: &str) -> io::Result<()> {
+        fs::remove_file(self.resolve(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    #[test]
+    fn test_filesystem() {
+        let root = tempfile::tempdir().unwrap();
+        let fs = FileSystem::new(root.path().join("root"));
+        fs::create_dir(root.path().join("root")).unwrap();
+        fs::write(root.path().join("secret"), "x").unwrap();
+        fs.open("a.txt", O_WRONLY | O_CREAT).unwrap().write_all(b"hi").unwrap();
+        let mut content = String::new();
+        fs.open("./dir/../a.txt", O_RDONLY).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "hi");
+        for path in ["../secret", "/etc/passwd", "a.txt/../../secret", ""] {
+            assert!(fs.open(path, O_RDONLY).is_err(), "{}", path);
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.path().join("secret"), root.path().join("root/link")).unwrap();
+            assert_eq!(fs.open("link", O_RDONLY).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+            std::os::unix::fs::symlink(root.path().join("outside"), root.path().join("root/dangling")).unwrap();
+            assert_eq!(fs.open("dangling", O_WRONLY | O_CREAT).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+            assert!(!root.path().join("outside").exists());
+        }
+        fs.unlink("a.txt").unwrap();
+        assert!(fs.open("a.txt", O_RDONLY).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 /*
 Memory layout:
 0-499 os stuff:
@@ -6,14 +8,30 @@ Memory layout:
     - 201 COD - char out data
     - 202 CIS - char in status
     - 203 CID - char in data
+    - 204 YLD - yield
+    - 205 SYS - system call number
+    - 206-208 SA1-SA3 - system call arguments
+    - 209 SRV - system call return value
+    - 210-217 LDS-LSE - the memory layout, the start & end (exclusive) of the data, code, heap & stack regions
     
     to write a char, write its ascii value to COD & then set COS to 1
     to read a char, set CIS to 1 & read ascii value from CID
+    to let the next process run, set YLD to 1
+    to make a system call, write its arguments to SA1-SA3 & then its number to SYS, see syscalls.rs
+    the layout registers are set by the OS, programs read them to find the regions, e.g malloc finds the heap's end
 500-999 data
 1000-3999 code
 4000-5999 heap
 6000-9999 stack
 
+These are the regions of the default MemoryLayout, the OS may be given a different one:
+the regions keep their order, starting at 500, with the given sizes.
+Executables are linked against the default layout & relocated to where they're loaded,
+an executable whose code or data doesn't fit its region isn't loaded.
+
+Each process is loaded into a free part of the code, data & stack regions,
+a single process that's loaded at given addresses gets the whole stack region.
+
 
 Stack frame:
 local vars...
@@ -54,9 +72,83 @@ Returning from the function:
 pub const PROGRAM_INIT_ADDRESS: u32 = 1000;
 pub const DATA_INIT_ADDRESS: u32 = 500;
 pub const INIT_SP_ADDRESS: u32 = 9999;
+pub const HEAP_INIT_ADDRESS: u32 = 4000;
+pub const HEAP_END_ADDRESS: u32 = 6000; // exclusive, the stack grows down to here
+pub const PROCESS_STACK_SIZE: u32 = 1000; // stack of a process that's spawned next to others
+pub const OS_RESERVED_SIZE: u32 = 500; // the first region starts after the OS's memory
+
+/// sizes of the memory regions, in words
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryLayout {
+    pub data_size: u32,
+    pub code_size: u32,
+    pub heap_size: u32,
+    pub stack_size: u32,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        MemoryLayout {
+            data_size: PROGRAM_INIT_ADDRESS - DATA_INIT_ADDRESS,
+            code_size: HEAP_INIT_ADDRESS - PROGRAM_INIT_ADDRESS,
+            heap_size: HEAP_END_ADDRESS - HEAP_INIT_ADDRESS,
+            stack_size: INIT_SP_ADDRESS + 1 - HEAP_END_ADDRESS,
+        }
+    }
+}
+
+impl MemoryLayout {
+    pub fn data(&self) -> Range<u32> {
+        OS_RESERVED_SIZE..OS_RESERVED_SIZE + self.data_size
+    }
+
+    pub fn code(&self) -> Range<u32> {
+        let start = self.data().end;
+        start..start + self.code_size
+    }
+
+    pub fn heap(&self) -> Range<u32> {
+        let start = self.code().end;
+        start..start + self.heap_size
+    }
+
+    pub fn stack(&self) -> Range<u32> {
+        let start = self.heap().end;
+        start..start + self.stack_size
+    }
+
+    /// the values of the layout registers, LDS-LSE
+    pub fn registers(&self) -> [u32; 8] {
+        let [data, code, heap, stack] = [self.data(), self.code(), self.heap(), self.stack()];
+        [data.start, data.end, code.start, code.end, heap.start, heap.end, stack.start, stack.end]
+    }
+}
 
 // memory mapped registers for io
 pub const COS : u32 = 200; // char out status
 pub const COD : u32 = 201; // char out data
 pub const CIS : u32 = 202; // char in status
 pub const CID : u32 = 203; // char in data
+pub const YLD : u32 = 204; // yield
+pub const SYS : u32 = 205; // system call number
+pub const SA1 : u32 = 206; // system call arguments
+pub const SA2 : u32 = 207;
+pub const SA3 : u32 = 208;
+pub const SRV : u32 = 209; // system call return value
+pub const LDS : u32 = 210; // first of the layout registers: data start, data end, code start, ... stack end
+pub const LSE : u32 = 217; // last layout register, stack end
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_layout() {
+        let layout = MemoryLayout::default();
+        assert_eq!(layout.data(), DATA_INIT_ADDRESS..PROGRAM_INIT_ADDRESS);
+        assert_eq!(layout.code(), PROGRAM_INIT_ADDRESS..HEAP_INIT_ADDRESS);
+        assert_eq!(layout.heap(), HEAP_INIT_ADDRESS..HEAP_END_ADDRESS);
+        assert_eq!(layout.stack(), HEAP_END_ADDRESS..INIT_SP_ADDRESS + 1);
+        let layout = MemoryLayout { data_size: 10, code_size: 20, heap_size: 30, stack_size: 40 };
+        assert_eq!(layout.registers(), [500, 510, 510, 530, 530, 560, 560, 600]);
+    }
+}
@@ -0,0 +1,627 @@
+use super::assembler::*;
+use super::layout::{MemoryLayout, DATA_INIT_ADDRESS, PROGRAM_INIT_ADDRESS};
+use crate::cpu::instructions::*;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
+
+pub const LINE_SYMBOL_PREFIX: &str = "_LINE_"; // symbols of the source lines' addresses, for setting breakpoints in the debugger
+
+/// a static library, i.e a collection of object files
+/// a member is linked only if it defines a symbol that is referenced but not yet defined
+#[derive(Debug, Clone)]
+pub struct Archive {
+    pub members: Vec<ObjectFile>,
+}
+
+/// a symbol placed by the linker
+#[derive(Debug, PartialEq, Clone)]
+pub struct MapEntry {
+    pub label: String,
+    pub addr: u32, // absolute
+    pub size: u32,
+    pub program: String, // name of the object that defines it
+    pub binding: Binding,
+}
+
+/// where the linker placed every symbol & how much of the memory layout the executable uses
+#[derive(Debug, Clone)]
+pub struct LinkMap {
+    pub code_symbols: Vec<MapEntry>, // sorted by address
+    pub data_symbols: Vec<MapEntry>, // sorted by address
+    pub code_size: u32,
+    pub data_size: u32,
+    pub layout: MemoryLayout, // the executable is loaded at the start of its code & data regions
+}
+
+impl LinkMap {
+    /// the map of the executable loaded into the given layout, the linker maps it into the default one
+    pub fn with_layout(mut self, layout: MemoryLayout) -> LinkMap {
+        let (code_start, data_start) = (self.layout.code().start, self.layout.data().start);
+        for entry in self.code_symbols.iter_mut() {
+            entry.addr = entry.addr - code_start + layout.code().start;
+        }
+        for entry in self.data_symbols.iter_mut() {
+            entry.addr = entry.addr - data_start + layout.data().start;
+        }
+        self.layout = layout;
+        self
+    }
+}
+
+fn region_usage(f: &mut std::fmt::Formatter, name: &str, used: u32, region: Range<u32>) -> std::fmt::Result {
+    let capacity = region.end - region.start;
+    let percent = (used * 100).checked_div(capacity).map_or("-".to_string(), |percent| format!("{}%", percent));
+    write!(f, "  {:<5} {:>6} / {} words ({}) at {}-{}", name, used, capacity, percent, region.start, region.end - 1)?;
+    if used > capacity {
+        write!(f, " OVERFLOW by {} words", used - capacity)?;
+    }
+    writeln!(f)
+}
+
+fn map_entries(f: &mut std::fmt::Formatter, entries: &[MapEntry]) -> std::fmt::Result {
+    writeln!(f, "  {:>7} {:>6}  {:<20} symbol", "address", "size", "program")?;
+    for entry in entries.iter() {
+        let binding = match entry.binding {
+            Binding::Global => "",
+            Binding::Local => " (local)",
+            Binding::Weak => " (weak)",
+        };
+        writeln!(f, "  {:>7} {:>6}  {:<20} {}{}", entry.addr, entry.size, entry.program, entry.label, binding)?;
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for LinkMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Code symbols:")?;
+        map_entries(f, &self.code_symbols)?;
+        writeln!(f, "\nData symbols:")?;
+        map_entries(f, &self.data_symbols)?;
+        writeln!(f, "\nMemory usage:")?;
+        region_usage(f, "code", self.code_size, self.layout.code())?;
+        region_usage(f, "data", self.data_size, self.layout.data())?;
+        let heap = self.layout.heap();
+        writeln!(f, "  heap  {} words at {}-{}, allocated at run time", heap.end - heap.start, heap.start, heap.end - 1)
+    }
+}
+
+fn map_entries_of(objects: &[ObjectFile], bases: &[u32], region_start: u32, symbols_of: fn(&ObjectFile) -> &HashMap<String, Symbol>) -> Vec<MapEntry> {
+    let mut entries = Vec::new();
+    for (object, base) in objects.iter().zip(bases.iter()) {
+        for (label, symbol) in symbols_of(object).iter() {
+            entries.push(MapEntry {
+                label: label.clone(),
+                addr: region_start + base + symbol.addr,
+                size: symbol.size,
+                program: object.name.clone(),
+                binding: symbol.binding.clone(),
+            });
+        }
+    }
+    entries.sort_by(|a, b| (a.addr, &a.label).cmp(&(b.addr, &b.label)));
+    entries
+}
+
+/// returns instr with its label operand replaced by value
+fn patch(instr: &Instruction, value: i32) -> Instruction {
+    match instr {
+        Instruction::Flow { op, .. } => Instruction::Flow { op: op.clone(), offset: value },
+        Instruction::Data { op, dst, .. } => Instruction::Data { op: op.clone(), dst: dst.clone(), src: RegOrImm::Val(value) },
+        Instruction::BinArith { op, dst, arg1, .. } => {
+            Instruction::BinArith { op: op.clone(), dst: dst.clone(), arg1: arg1.clone(), arg2: RegOrImm::Val(value) }
+        }
+        Instruction::Test { op, arg1, .. } => Instruction::Test { op: op.clone(), arg1: arg1.clone(), arg2: RegOrImm::Val(value) },
+        _ => panic!("instruction cannot be relocated: {:?}", instr),
+    }
+}
+
+/// the immediate operand of a relocatable instruction
+pub(super) fn immediate(instr: &Instruction) -> Option<i32> {
+    match instr {
+        Instruction::Flow { offset, .. } => Some(*offset),
+        Instruction::Data { src: RegOrImm::Val(value), .. }
+        | Instruction::BinArith { arg2: RegOrImm::Val(value), .. }
+        | Instruction::Test { arg2: RegOrImm::Val(value), .. } => Some(*value),
+        _ => None,
+    }
+}
+
+/// returns the code & data of an executable with their absolute addresses adjusted for loading code at code_base & data at data_base
+pub fn rebase(exec: &Executable, code_base: u32, data_base: u32) -> (Vec<Instruction>, Vec<i32>) {
+    let mut code = exec.code.clone();
+    let mut data = exec.data.clone();
+    for reloc in exec.relocations.iter() {
+        let delta = match reloc.target {
+            Segment::Code => code_base as i32 - PROGRAM_INIT_ADDRESS as i32,
+            Segment::Data => data_base as i32 - DATA_INIT_ADDRESS as i32,
+        };
+        let offset = reloc.offset as usize;
+        match reloc.segment {
+            Segment::Code => {
+                let value = immediate(&code[offset]).expect("load relocation of an instruction without an immediate operand");
+                code[offset] = patch(&code[offset], value + reloc.count * delta);
+            }
+            Segment::Data => data[offset] += reloc.count * delta,
+        }
+    }
+    (code, data)
+}
+
+fn link_error(object_i: usize, loc: &SourceLoc, cause: AsmErrorCause) -> AsmError {
+    AsmError {
+        program: object_i,
+        line: loc.line,
+        column: loc.column,
+        cause,
+    }
+}
+
+/// exported symbols by label, with the index of the object that defines them
+type SymbolTable = HashMap<String, (usize, Symbol)>;
+
+/// adds the global & weak symbols of objects[object_i] to a global table
+/// a global symbol replaces a weak one, a weak symbol is ignored if the label is already defined,
+/// returns errors for global symbols that are already defined as global
+fn add_symbols(table: &mut SymbolTable, objects: &[ObjectFile], object_i: usize, symbols: &HashMap<String, Symbol>, base: u32) -> Vec<AsmError> {
+    let mut errors = Vec::new();
+    let mut symbols: Vec<(&String, &Symbol)> = symbols.iter().filter(|(_, symbol)| symbol.binding != Binding::Local).collect();
+    symbols.sort_by_key(|(_, symbol)| (symbol.loc.line, symbol.loc.column));
+    for (label, symbol) in symbols {
+        match table.get(label) {
+            Some(_) if symbol.binding == Binding::Weak => continue,
+            Some((_, defined)) if defined.binding == Binding::Weak => {}
+            Some((defined_i, _)) => {
+                let cause = AsmErrorCause::MultipleDefinition {
+                    label: label.clone(),
+                    first: objects[*defined_i].name.clone(),
+                    second: objects[object_i].name.clone(),
+                };
+                errors.push(link_error(object_i, &symbol.loc, cause));
+                continue;
+            }
+            None => {}
+        }
+        table.insert(label.clone(), (object_i, Symbol { addr: base + symbol.addr, ..symbol.clone() }));
+    }
+    errors
+}
+
+/// where the objects being linked are placed & the symbols they export
+struct Layout<'a> {
+    objects: &'a [ObjectFile],
+    code_bases: Vec<u32>,
+    data_bases: Vec<u32>,
+    code_table: SymbolTable,
+    data_table: SymbolTable,
+}
+
+impl<'a> Layout<'a> {
+    /// finds the symbol a label of a relocation of object_i refers to, with its address relative to the start of the code or data
+    /// local symbols of the object take precedence, returns whether it's a data symbol
+    fn find(&self, object_i: usize, kind: &RelocationKind, label: &str) -> Option<(bool, Symbol)> {
+        let object = &self.objects[object_i];
+        let local = |symbols: &HashMap<String, Symbol>, base: u32| {
+            symbols.get(label)
+                .filter(|symbol| symbol.binding == Binding::Local)
+                .map(|symbol| Symbol { addr: base + symbol.addr, ..symbol.clone() })
+        };
+        let code = local(&object.code_symbols, self.code_bases[object_i]).or_else(|| self.code_table.get(label).map(|(_, symbol)| symbol.clone()));
+        if *kind == RelocationKind::CodeOffset {
+            return code.map(|symbol| (false, symbol));
+        }
+        let data = local(&object.data_symbols, self.data_bases[object_i]).or_else(|| self.data_table.get(label).map(|(_, symbol)| symbol.clone()));
+        data.map(|symbol| (true, symbol)).or_else(|| code.map(|symbol| (false, symbol)))
+    }
+
+    /// the value to patch a relocation of object_i with, or the first label that's undefined
+    /// also returns how many absolute code & data addresses the value holds, see LoadRelocation
+    fn evaluate(&self, object_i: usize, reloc: &Relocation) -> Result<(i32, i32, i32), String> {
+        let (mut value, mut code_count, mut data_count) = (0, 0, 0);
+        for (sign, term) in reloc.expr.terms.iter() {
+            let term_value = match term {
+                Term::Const(val) => *val,
+                Term::Label(label) => match self.find(object_i, &reloc.kind, label).ok_or_else(|| label.clone())? {
+                    (_, symbol) if reloc.kind == RelocationKind::CodeOffset => symbol.addr as i32,
+                    (true, symbol) => {
+                        data_count += sign;
+                        (DATA_INIT_ADDRESS + symbol.addr) as i32
+                    }
+                    (false, symbol) => {
+                        code_count += sign;
+                        (PROGRAM_INIT_ADDRESS + symbol.addr) as i32
+                    }
+                },
+                Term::SizeOf(label) => self.find(object_i, &reloc.kind, label).ok_or_else(|| label.clone())?.1.size as i32,
+            };
+            value += sign * term_value;
+        }
+        if reloc.kind == RelocationKind::CodeOffset {
+            value -= (self.code_bases[object_i] + reloc.offset) as i32;
+        }
+        Ok((value, code_count, data_count))
+    }
+}
+
+/// links object files into an executable
+/// objects are laid out in the given order, execution starts at the beginning of the first object
+/// errors refer to objects by their index in objects
+pub fn link(objects: &[ObjectFile]) -> Result<Executable, Vec<AsmError>> {
+    link_with_map(objects).map(|(exec, _)| exec)
+}
+
+/// links object files into an executable, also returns where every symbol was placed
+pub fn link_with_map(objects: &[ObjectFile]) -> Result<(Executable, LinkMap), Vec<AsmError>> {
+    let mut layout = Layout {
+        objects,
+        code_bases: Vec::new(),
+        data_bases: Vec::new(),
+        code_table: HashMap::new(),
+        data_table: HashMap::new(),
+    };
+    let mut code_base = 0;
+    let mut data_base = 0;
+    let mut errors = Vec::new();
+
+    // first pass, lay out objects one after the other & collect their symbols
+    for (object_i, object) in objects.iter().enumerate() {
+        let misalignment = (DATA_INIT_ADDRESS + data_base) % object.data_align;
+        data_base += (object.data_align - misalignment) % object.data_align;
+        errors.extend(add_symbols(&mut layout.code_table, objects, object_i, &object.code_symbols, code_base));
+        errors.extend(add_symbols(&mut layout.data_table, objects, object_i, &object.data_symbols, data_base));
+        layout.code_bases.push(code_base);
+        layout.data_bases.push(data_base);
+        code_base += object.code.len() as u32;
+        data_base += object.data.len() as u32;
+    }
+
+    // second pass, copy code & data and resolve relocations
+    let mut code = Vec::new();
+    let mut data = Vec::new();
+    let mut symbol_table: HashMap<String, u32> = layout.code_table.iter().map(|(label, (_, symbol))| (label.clone(), symbol.addr)).collect();
+    let data_table = layout.data_table.iter().map(|(label, (_, symbol))| (label.clone(), symbol.addr)).collect();
+    // local functions aren't exported, they're named for locating addresses, e.g in backtraces
+    for (object_i, object) in objects.iter().enumerate() {
+        let mut locals: Vec<(&String, &Symbol)> = object.code_symbols.iter()
+            .filter(|(label, symbol)| symbol.binding == Binding::Local && !is_temporary_label(label))
+            .collect();
+        locals.sort_by_key(|(_, symbol)| symbol.addr);
+        for (label, symbol) in locals {
+            let name = if symbol_table.contains_key(label) { format!("{}@{}", label, object.name) } else { label.clone() };
+            symbol_table.insert(name, layout.code_bases[object_i] + symbol.addr);
+        }
+    }
+    let mut load_relocations = Vec::new();
+    let mut whole_program_line_i = 0;
+    for (object_i, object) in objects.iter().enumerate() {
+        let code_base = layout.code_bases[object_i];
+        code.extend(object.code.iter().cloned());
+        data.resize(layout.data_bases[object_i] as usize, 0); // padding for the object's alignment
+        data.extend(object.data.iter().cloned());
+        for reloc in object.relocations.iter() {
+            match layout.evaluate(object_i, reloc) {
+                Ok((value, code_count, data_count)) => {
+                    let addr = match reloc.segment {
+                        Segment::Code => {
+                            let instr_addr = (code_base + reloc.offset) as usize;
+                            code[instr_addr] = patch(&code[instr_addr], value);
+                            instr_addr
+                        }
+                        Segment::Data => {
+                            let word_addr = (layout.data_bases[object_i] + reloc.offset) as usize;
+                            data[word_addr] = value;
+                            word_addr
+                        }
+                    };
+                    for (target, count) in [(Segment::Code, code_count), (Segment::Data, data_count)] {
+                        if count != 0 {
+                            load_relocations.push(LoadRelocation { offset: addr as u32, segment: reloc.segment, target, count });
+                        }
+                    }
+                }
+                Err(label) => errors.push(link_error(object_i, &reloc.loc, AsmErrorCause::UndefinedLabel(label))),
+            }
+        }
+        for line_addr in object.line_addrs.iter() {
+            symbol_table.insert(format!("{}{}", LINE_SYMBOL_PREFIX, whole_program_line_i), code_base + line_addr);
+            whole_program_line_i += 1;
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let map = LinkMap {
+        code_symbols: map_entries_of(objects, &layout.code_bases, PROGRAM_INIT_ADDRESS, |object| &object.code_symbols),
+        data_symbols: map_entries_of(objects, &layout.data_bases, DATA_INIT_ADDRESS, |object| &object.data_symbols),
+        code_size: code.len() as u32,
+        data_size: data.len() as u32,
+        layout: MemoryLayout::default(),
+    };
+    let exec = Executable {
+        code,
+        data,
+        symbol_table,
+        data_table,
+        entry_point: 0, // execution starts at the beginning of the first object
+        relocations: load_relocations,
+    };
+    Ok((exec, map))
+}
+
+/// returns the symbols that are referenced by the given objects but aren't defined by any of them
+fn undefined_symbols(objects: &[ObjectFile]) -> Vec<(RelocationKind, String)> {
+    let mut undefined = Vec::new();
+    for object in objects.iter() {
+        for reloc in object.relocations.iter() {
+            for label in reloc.expr.labels() {
+                if !object.defines(&reloc.kind, label) && !objects.iter().any(|o| o.exports(&reloc.kind, label)) {
+                    undefined.push((reloc.kind.clone(), label.clone()));
+                }
+            }
+        }
+    }
+    undefined
+}
+
+/// links objects with the members of the given archives that are needed to resolve their undefined symbols
+/// needed archive members are placed after the objects
+pub fn link_with_archives(objects: &[ObjectFile], archives: &[Archive]) -> Result<Executable, Vec<AsmError>> {
+    link(&select_archive_members(objects, archives))
+}
+
+/// returns objects followed by the members of archives that are needed to resolve their undefined symbols
+pub fn select_archive_members(objects: &[ObjectFile], archives: &[Archive]) -> Vec<ObjectFile> {
+    let mut selected = objects.to_vec();
+    let mut selected_members = HashSet::new();
+    loop {
+        let undefined = undefined_symbols(&selected);
+        let mut needed_member = None;
+        'search: for (archive_i, archive) in archives.iter().enumerate() {
+            for (member_i, member) in archive.members.iter().enumerate() {
+                if selected_members.contains(&(archive_i, member_i)) {
+                    continue;
+                }
+                if undefined.iter().any(|(kind, symbol)| member.exports(kind, symbol)) {
+                    needed_member = Some((archive_i, member_i));
+                    break 'search;
+                }
+            }
+        }
+        match needed_member {
+            Some((archive_i, member_i)) => {
+                selected_members.insert((archive_i, member_i));
+                selected.push(archives[archive_i].members[member_i].clone());
+            }
+            None => break,
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_link_objects() {
+        let main = assemble_object("
+        LEA R1 s
+        CALL F
+        HALT
+        ", "main").unwrap();
+        let lib = assemble_object("
+        .stringz s hi
+        F:
+        LOAD R1 R1
+        RET
+        ", "lib").unwrap();
+        assert_eq!(main.undefined_symbols(), vec!["F".to_string(), "s".to_string()]);
+        assert_eq!(lib.undefined_symbols().len(), 0);
+        let exec = link(&[main, lib]).unwrap();
+        assert_eq!(*exec.symbol_table.get("F").unwrap(), 3);
+        assert_eq!(exec.code[0], Instruction::from_str(&format!("LEA R1 {}", DATA_INIT_ADDRESS)).unwrap());
+        assert_eq!(exec.code[1], Instruction::from_str("CALL 2").unwrap());
+    }
+    #[test]
+    fn test_link_with_archives() {
+        let main = assemble_object("
+        CALL F
+        HALT
+        ", "main").unwrap();
+        let f = assemble_object("
+        F:
+        CALL G
+        RET
+        ", "f").unwrap();
+        let g = assemble_object("
+        G:
+        RET
+        ", "g").unwrap();
+        let unused = assemble_object("
+        H:
+        RET
+        ", "unused").unwrap();
+        let archive = Archive { members: vec![unused, g, f] };
+        let exec = link_with_archives(&[main], &[archive]).unwrap();
+        // unused member is not linked, f is pulled in before g which it references
+        assert_eq!(exec.code.len(), 5);
+        assert_eq!(*exec.symbol_table.get("F").unwrap(), 2);
+        assert_eq!(*exec.symbol_table.get("G").unwrap(), 4);
+        assert!(!exec.symbol_table.contains_key("H"));
+    }
+    #[test]
+    fn test_symbol_visibility() {
+        let main = assemble_object("
+        CALL F
+        CALL helper
+        HALT
+        F:
+        RET
+        .local helper
+        helper:
+        RET
+        ", "main").unwrap();
+        let lib = assemble_object("
+        .weak F
+        F:
+        CALL helper
+        RET
+        .local helper
+        helper:
+        RET
+        ", "lib").unwrap();
+        // main's F overrides lib's weak one, each object calls its own helper
+        let exec = link(&[main.clone(), lib.clone()]).unwrap();
+        assert_eq!(*exec.symbol_table.get("F").unwrap(), 3);
+        assert_eq!(exec.code[1], Instruction::from_str("CALL 3").unwrap());
+        assert_eq!(exec.code[5], Instruction::from_str("CALL 2").unwrap());
+        let exec = link(&[lib.clone(), main.clone()]).unwrap();
+        assert_eq!(*exec.symbol_table.get("F").unwrap(), 6);
+        let errors = link(&[main.clone(), main]).err().unwrap();
+        let cause = AsmErrorCause::MultipleDefinition { label: "F".to_string(), first: "main".to_string(), second: "main".to_string() };
+        assert_eq!(errors, vec![AsmError { program: 1, line: 5, column: 9, cause }]);
+        assert!(link(&[lib.clone(), lib]).is_ok());
+    }
+    #[test]
+    fn test_rebase() {
+        let exec = link(&[assemble_object("
+        .word table 1 2
+        .word ptrs table+1 F
+        LEA R1 table+1
+        MOV R2 F
+        MOV R3 table-F
+        MOV R4 sizeof(table)
+        F:
+        HALT
+        ", "main").unwrap()]).unwrap();
+        assert_eq!(exec.relocations, vec![
+            LoadRelocation { offset: 2, segment: Segment::Data, target: Segment::Data, count: 1 },
+            LoadRelocation { offset: 3, segment: Segment::Data, target: Segment::Code, count: 1 },
+            LoadRelocation { offset: 0, segment: Segment::Code, target: Segment::Data, count: 1 },
+            LoadRelocation { offset: 1, segment: Segment::Code, target: Segment::Code, count: 1 },
+            LoadRelocation { offset: 2, segment: Segment::Code, target: Segment::Code, count: -1 },
+            LoadRelocation { offset: 2, segment: Segment::Code, target: Segment::Data, count: 1 },
+        ]);
+        let (code, data) = rebase(&exec, PROGRAM_INIT_ADDRESS + 100, DATA_INIT_ADDRESS + 10);
+        assert_eq!(data, vec![1, 2, (DATA_INIT_ADDRESS + 11) as i32, (PROGRAM_INIT_ADDRESS + 104) as i32]);
+        assert_eq!(code[0], Instruction::from_str(&format!("LEA R1 {}", DATA_INIT_ADDRESS + 11)).unwrap());
+        assert_eq!(code[1], Instruction::from_str(&format!("MOV R2 {}", PROGRAM_INIT_ADDRESS + 104)).unwrap());
+        assert_eq!(code[2], Instruction::from_str(&format!("MOV R3 {}", DATA_INIT_ADDRESS as i32 + 10 - (PROGRAM_INIT_ADDRESS as i32 + 104))).unwrap());
+        assert_eq!(code[3], Instruction::from_str("MOV R4 2").unwrap());
+    }
+    #[test]
+    fn test_data_alignment() {
+        let first = assemble_object("
+        .word a 1 2 3
+        ", "first").unwrap();
+        let second = assemble_object("
+        .word b 4
+        .align 8
+        .fill c 2 7
+        ", "second").unwrap();
+        assert_eq!(second.data, vec![4, 0, 0, 0, 0, 0, 0, 0, 7, 7]);
+        let exec = link(&[first, second]).unwrap();
+        // the second object's data starts at the first multiple of 8 after the first object's data
+        assert_eq!(*exec.data_table.get("b").unwrap() + DATA_INIT_ADDRESS, 504);
+        assert_eq!(*exec.data_table.get("c").unwrap() + DATA_INIT_ADDRESS, 512);
+        assert_eq!(exec.data[..5], [1, 2, 3, 0, 4]);
+    }
+    #[test]
+    fn test_link_map() {
+        let main = assemble_object("
+        .block buf 3
+        main:
+        CALL F
+        HALT
+        ", "main").unwrap();
+        let lib = assemble_object("
+        .stringz s hi
+        F:
+        .Lloop:
+        JUMP .Lloop
+        ", "lib").unwrap();
+        let (_, map) = link_with_map(&[main, lib]).unwrap();
+        let entry = |label: &str, addr, size, program: &str, binding| MapEntry {
+            label: label.to_string(), addr, size, program: program.to_string(), binding,
+        };
+        assert_eq!(map.code_symbols, vec![
+            entry("main", PROGRAM_INIT_ADDRESS, 2, "main", Binding::Global),
+            entry(".Lloop$F", PROGRAM_INIT_ADDRESS + 2, 1, "lib", Binding::Local),
+            entry("F", PROGRAM_INIT_ADDRESS + 2, 1, "lib", Binding::Global),
+        ]);
+        assert_eq!(map.data_symbols, vec![
+            entry("buf", DATA_INIT_ADDRESS, 3, "main", Binding::Global),
+            entry("s", DATA_INIT_ADDRESS + 3, 3, "lib", Binding::Global),
+        ]);
+        let report = map.to_string();
+        assert!(report.contains("code       3 / 3000 words (0%) at 1000-3999"));
+        assert!(report.contains("data       6 / 500 words (1%) at 500-999"));
+
+        let layout = MemoryLayout { data_size: 100, code_size: 10, heap_size: 50, stack_size: 100 };
+        let map = map.with_layout(layout);
+        assert_eq!(map.code_symbols[2].addr, 602);
+        assert_eq!(map.data_symbols[1].addr, 503);
+        let report = map.to_string();
+        assert!(report.contains("code       3 / 10 words (30%) at 600-609"));
+        assert!(report.contains("data       6 / 100 words (6%) at 500-599"));
+        assert!(report.contains("heap  50 words at 610-659"));
+        // an empty region has no percentage
+        let report = map.with_layout(MemoryLayout { data_size: 0, ..layout }).to_string();
+        assert!(report.contains("data       6 / 0 words (-) at 500-499 OVERFLOW by 6 words"));
+    }
+    #[test]
+    fn test_local_labels() {
+        let first = assemble_object("
+        .string .Ls \"a\"
+        F:
+        JUMP .Lend
+        .Lend:
+        LEA R1 .Ls
+        RET
+        ", "first").unwrap();
+        let second = assemble_object("
+        .string .Ls \"b\"
+        G:
+        JUMP .Lend
+        .Lend:
+        LEA R1 .Ls
+        RET
+        ", "second").unwrap();
+        let exec = link(&[first, second]).unwrap();
+        // local labels of different objects don't collide & aren't exported
+        assert!(!exec.symbol_table.keys().any(|label| label.starts_with(".L")));
+        assert!(!exec.data_table.contains_key(".Ls"));
+        assert_eq!(exec.code[0], Instruction::from_str("JUMP 1").unwrap());
+        assert_eq!(exec.code[4], Instruction::from_str(&format!("LEA R1 {}", DATA_INIT_ADDRESS + 2)).unwrap());
+    }
+    #[test]
+    fn test_local_functions() {
+        let first = assemble_object("
+        .local H
+        F:
+        CALL H
+        RET
+        H:
+        RET
+        ", "first").unwrap();
+        let second = assemble_object("
+        .local F
+        .local H
+        G:
+        CALL F
+        RET
+        F:
+        CALL H
+        RET
+        H:
+        RET
+        ", "second").unwrap();
+        let exec = link(&[first, second]).unwrap();
+        // local functions are named, after the global ones & the locals of the previous objects
+        assert_eq!(*exec.symbol_table.get("F").unwrap(), 0);
+        assert_eq!(*exec.symbol_table.get("H").unwrap(), 2);
+        assert_eq!(*exec.symbol_table.get("G").unwrap(), 3);
+        assert_eq!(*exec.symbol_table.get("F@second").unwrap(), 5);
+        assert_eq!(*exec.symbol_table.get("H@second").unwrap(), 7);
+        assert_eq!(exec.code[5], Instruction::from_str("CALL 2").unwrap());
+    }
+}
@@ -1,42 +1,127 @@
+pub mod asm_macros;
 pub mod assembler;
+pub mod backtrace;
 pub mod compiler;
+pub mod core_dump;
+pub mod dead_code;
+pub mod exec_file;
+pub mod filesystem;
 pub mod layout;
+pub mod linker;
+pub mod process;
+pub mod profiler;
+pub mod run;
+pub mod streams;
+pub mod syscalls;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Instant;
 
-use self::assembler::assemble;
 use self::assembler::assemble_and_link;
+use self::backtrace::{backtrace, format_backtrace, Frame};
+use self::assembler::assemble_and_link_with_map;
+use self::assembler::format_errors;
+use self::assembler::AsmError;
 use self::assembler::Executable;
-use self::compiler::Compiler;
+use self::compiler::{CompileOptions, Compiler};
+use self::core_dump::CoreDump;
+use self::exec_file::save_core;
+use self::filesystem::FileSystem;
 use self::layout::*;
+use self::linker::{rebase, LinkMap, LINE_SYMBOL_PREFIX};
+use self::process::*;
+use self::profiler::{Profile, UNKNOWN_FUNCTION};
+use self::run::*;
+use self::streams::{Input, Output};
 use crate::cpu::instructions::*;
 use crate::cpu::Cpu;
+use crate::cpu::Fault;
+use crate::cpu::FlowEvent;
 use crate::cpu::MemEntry;
+use crate::cpu::Registers;
 
 
+fn exec_or_panic(res: Result<Executable, Vec<AsmError>>) -> Executable {
+    match res {
+        Ok(exec) => exec,
+        Err(errors) => panic!("assembly failed:\n{}", format_errors(&errors)),
+    }
+}
+
+/// settings chosen when the OS is constructed
+#[derive(Clone, Debug, Default)]
+pub struct OsConfig {
+    pub fs_root: Option<PathBuf>, // host directory backing the files programs open, without it open fails
+    pub layout: MemoryLayout,
+    pub core_dir: Option<PathBuf>, // host directory where a faulting process's core file is written, see core_dump.rs
+}
 
 pub struct OS {
     pub cpu: Cpu,
-    pub out_chars : Vec<char>,
-    pub inp_chars : Vec<char>,
-    std_programs: Vec<String>,
-    compiled_programs_count: u32, // hack to keep compiler tmp labels from colliding
+    pub stdin: Input, // the streams of the programs the OS runs, the host's by default
+    pub stdout: Output,
+    pub stderr: Output,
+    std_programs: Option<Vec<String>>, // compiled on first use, running a prebuilt executable doesn't need them
+    pub compile_options: CompileOptions, // used by compile, libc is always compiled with the defaults
+    pub quantum: u32, // # of instructions a process runs before the scheduler switches to the next one
+    pub limits: RunLimits, // limits of every run, none by default
+    pub args: Vec<String>, // argv of the programs the OS loads, conventionally argv[0] is the program's name
+    pub env: Vec<String>, // envp of the programs the OS loads, "NAME=value" strings
+    pub profile: Option<Profile>, // the executed instructions are profiled into it while it's set, see profiler.rs
+    processes: Vec<Process>,
+    current: Option<usize>, // index of the running process
+    next_pid: Pid,
+    slice_steps: u32, // # of instructions the running process executed in its time slice
+    instructions: u64, // # of instructions executed since the memory was cleared
+    stdout_copy: Vec<u8>, // what was written to stdout since the memory was cleared
+    yield_requested: bool,
+    exit_requested: Option<i32>, // exit status of the running process if it called exit
+    fs: Option<FileSystem>,
+    layout: MemoryLayout,
+    core_dir: Option<PathBuf>,
 }
 
 impl OS {
-    pub fn new() -> OS {
-        let mut std_programs = Vec::new();
-        let num_std_programs = 1;
-        std_programs.push(Compiler::compile("libc/libc.c", 0));
-        assert_eq!(std_programs.len() as u32, num_std_programs);
-        let mut instance = OS { cpu: Cpu::new() , out_chars: Vec::new(), inp_chars: Vec::new(),
-            std_programs, compiled_programs_count: num_std_programs};
+    /// an OS with the given memory layout & the default settings
+    pub fn new(layout: MemoryLayout) -> OS {
+        OS::with_config(OsConfig { layout, ..OsConfig::default() })
+    }
+
+    pub fn with_config(config: OsConfig) -> OS {
+        let mut instance = OS { cpu: Cpu::new(), stdin: Input::Terminal, stdout: Output::Terminal, stderr: Output::TerminalErr,
+            std_programs: None, compile_options: CompileOptions::default(), quantum: DEFAULT_QUANTUM, limits: RunLimits::default(),
+            args: Vec::new(), env: Vec::new(), profile: None,
+            processes: Vec::new(), current: None, next_pid: 1, slice_steps: 0, instructions: 0, stdout_copy: Vec::new(), yield_requested: false,
+            exit_requested: None, fs: config.fs_root.map(FileSystem::new), layout: config.layout,
+            core_dir: config.core_dir};
         instance.initialize_memory();
         instance
     }
 
+    fn get_std_programs(&mut self) -> &Vec<String> {
+        if self.std_programs.is_none() {
+            self.std_programs = Some(vec![Compiler::compile("libc/libc.c")]);
+        }
+        self.std_programs.as_ref().unwrap()
+    }
+
+    /// assembles the given programs & links them with the std programs
+    pub fn link_with_std(&mut self, programs: Vec<&str>) -> Result<Executable, Vec<AsmError>> {
+        self.link_with_std_and_map(programs).map(|(exec, _)| exec)
+    }
+
+    /// like link_with_std, also returns the link map of the executable
+    pub fn link_with_std_and_map(&mut self, programs: Vec<&str>) -> Result<(Executable, LinkMap), Vec<AsmError>> {
+        let mut programs_with_std = programs;
+        let std_programs = self.get_std_programs().clone();
+        programs_with_std.extend(std_programs.iter().map(|s| s.as_str()));
+        assemble_and_link_with_map(programs_with_std).map(|(exec, map)| (exec, map.with_layout(self.layout)))
+    }
+
     fn initialize_memory(&mut self) {
         self.cpu.mem.set(
             0,
@@ -46,101 +131,390 @@ impl OS {
         self.cpu.mem.set(COD, MemEntry::Num(0));
         self.cpu.mem.set(CIS, MemEntry::Num(0));
         self.cpu.mem.set(CID, MemEntry::Num(0));
+        self.cpu.mem.set(YLD, MemEntry::Num(0));
+        for addr in [SYS, SA1, SA2, SA3, SRV] {
+            self.cpu.mem.set(addr, MemEntry::Num(0));
+        }
+        for (addr, value) in (LDS..=LSE).zip(self.layout.registers()) {
+            self.cpu.mem.set(addr, MemEntry::Num(value as i32));
+        }
     }
 
+    /// clears the memory & drops all processes
     fn reset_cpu_state(&mut self) {
         self.cpu = Cpu::new();
         self.initialize_memory();
+        self.processes.clear();
+        self.current = None;
+        self.instructions = 0;
+        self.stdout_copy.clear();
     }
 
-    fn initialize_stackframe(&mut self) {
-        self.cpu
-            .regs
-            .set(&Register::SP, (INIT_SP_ADDRESS - 3) as i32);
-        self.cpu
-            .regs
-            .set(&Register::BP, (INIT_SP_ADDRESS - 2) as i32);
+    /// sets up the initial frame of a process at the top of its stack,
+    /// above it are main's arguments argc, argv & envp, the NULL terminated argv & envp arrays & their strings
+    fn initialize_stackframe(&mut self, regs: &mut Registers, stack: &Range<u32>) {
+        let strings_size: usize = self.args.iter().chain(self.env.iter()).map(|s| s.len() + 1).sum();
+        let args_size = (strings_size + self.args.len() + self.env.len() + 5) as u32;
+        assert!(args_size + 3 < stack.len() as u32, "the arguments & environment don't fit the stack");
+        let mut addr = stack.end;
+        let mut string_addrs = Vec::new();
+        for string in self.args.iter().chain(self.env.iter()) {
+            addr -= string.len() as u32 + 1;
+            for (i, c) in string.bytes().chain([0]).enumerate() {
+                self.cpu.mem.set(addr + i as u32, MemEntry::Num(c as i32));
+            }
+            string_addrs.push(addr as i32);
+        }
+        let (arg_addrs, env_addrs) = string_addrs.split_at(self.args.len());
+        let envp = addr - env_addrs.len() as u32 - 1;
+        let argv = envp - arg_addrs.len() as u32 - 1;
+        for (array, addrs) in [(argv, arg_addrs), (envp, env_addrs)] {
+            for (i, string_addr) in addrs.iter().chain([&0]).enumerate() {
+                self.cpu.mem.set(array + i as u32, MemEntry::Num(*string_addr));
+            }
+        }
+        let stack_top = argv - 4;
+        for (i, arg) in [self.args.len() as u32, argv, envp].iter().enumerate() {
+            self.cpu.mem.set(stack_top + 1 + i as u32, MemEntry::Num(*arg as i32));
+        }
+
+        regs.set(&Register::SP, (stack_top - 3) as i32);
+        regs.set(&Register::BP, (stack_top - 2) as i32);
 
-        self.cpu.mem.set(INIT_SP_ADDRESS - 1, MemEntry::Num(0)); // jump to HALT in the end
+        self.cpu.mem.set(stack_top - 1, MemEntry::Num(0)); // jump to HALT in the end
         self.cpu.mem.set(
-            INIT_SP_ADDRESS - 2,
-            MemEntry::Num((INIT_SP_ADDRESS - 2) as i32),
+            stack_top - 2,
+            MemEntry::Num((stack_top - 2) as i32),
         ); // no prev BP, BP points to itself
-        self.cpu.mem.set(INIT_SP_ADDRESS, MemEntry::Num(-1)); // deafult return value = -1
+        self.cpu.mem.set(stack_top, MemEntry::Num(-1)); // deafult return value = -1
     }
 
-    fn load_program(&mut self, instructions: &Vec<Instruction>, data: &Vec<i32>) {
+    /// loads the executable's code at code_base & its data at data_base, adjusting its absolute addresses accordingly
+    fn load_program(&mut self, exec: &Executable, code_base: u32, data_base: u32) {
+        let (code, data) = rebase(exec, code_base, data_base);
         // load instructions
-        for (instr_i, instr) in instructions.iter().enumerate() {
+        for (instr_i, instr) in code.into_iter().enumerate() {
             self.cpu.mem.set(
-                PROGRAM_INIT_ADDRESS + (instr_i as u32),
-                MemEntry::Instruction(instr.clone()),
+                code_base + (instr_i as u32),
+                MemEntry::Instruction(instr),
             );
         }
         // load data
-        for (data_i, data) in data.iter().enumerate() {
+        for (data_i, data) in data.into_iter().enumerate() {
             self.cpu.mem.set(
-                DATA_INIT_ADDRESS + (data_i as u32),
-                MemEntry::Num(data.clone()),
+                data_base + (data_i as u32),
+                MemEntry::Num(data),
             );
         }
     }
 
+    pub fn layout(&self) -> &MemoryLayout {
+        &self.layout
+    }
+
+    /// loads the executable at the given addresses & adds a ready process for it
+    /// fails if the executable's code or data doesn't fit its region at those addresses
+    fn create_process(&mut self, exec: &Executable, code_base: u32, data_base: u32, stack: Range<u32>) -> Result<Pid, LoadError> {
+        check_fits(&self.layout.code(), code_base, exec.code.len() as u32, "code")?;
+        check_fits(&self.layout.data(), data_base, exec.data.len() as u32, "data")?;
+        self.load_program(exec, code_base, data_base);
+        let mut regs = Registers::new();
+        regs.set(&Register::IR, (code_base + exec.entry_point) as i32);
+        self.initialize_stackframe(&mut regs, &stack);
+        let initial_sp = regs.get(&Register::SP) as u32;
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        let symbols = loaded_symbols(exec.symbol_table.iter().filter(|(name, _)| !name.starts_with(LINE_SYMBOL_PREFIX)), code_base);
+        let data_symbols = loaded_symbols(exec.data_table.iter(), data_base);
+        self.processes.push(Process {
+            pid,
+            state: ProcessState::Ready,
+            code: code_base..code_base + exec.code.len() as u32,
+            data: data_base..data_base + exec.data.len() as u32,
+            stack,
+            brk: self.layout.heap().start,
+            peak_stack_depth: 0,
+            peak_heap_usage: 0,
+            initial_sp,
+            files: HashMap::new(),
+            symbols,
+            data_symbols,
+            regs,
+        });
+        Ok(pid)
+    }
+
+    /// start of a free part of region that's size words long, the parts used by running processes aren't free
+    fn allocate(&self, region: Range<u32>, size: u32, segment: &'static str, range_of: fn(&Process) -> &Range<u32>) -> Result<u32, LoadError> {
+        let mut used: Vec<Range<u32>> = self.processes.iter().filter(|process| process.is_alive()).map(|process| range_of(process).clone()).collect();
+        used.sort_by_key(|range| range.start);
+        first_fit(&region, &used, size).ok_or(LoadError::NoRoom { segment, size })
+    }
+
+    /// loads the executable as a new process next to the loaded ones, the processes run when run_processes is called
+    pub fn spawn(&mut self, exec: &Executable) -> Result<Pid, LoadError> {
+        let code_base = self.allocate(self.layout.code(), exec.code.len() as u32, "code", |process| &process.code)?;
+        let data_base = self.allocate(self.layout.data(), exec.data.len() as u32, "data", |process| &process.data)?;
+        let stack_start = self.allocate(self.layout.stack(), PROCESS_STACK_SIZE, "stack", |process| &process.stack)?;
+        self.create_process(exec, code_base, data_base, stack_start..stack_start + PROCESS_STACK_SIZE)
+    }
+
+    pub fn processes(&self) -> &[Process] {
+        &self.processes
+    }
+
+    /// exit status of the process, None if it's still running or doesn't exist
+    pub fn exit_status(&self, pid: Pid) -> Option<i32> {
+        match self.processes.iter().find(|process| process.pid == pid)?.state {
+            ProcessState::Exited(status) => Some(status),
+            ProcessState::Ready | ProcessState::Faulted(_) => None,
+        }
+    }
+
+    /// switches to the next ready process after the running one, in round-robin order
+    /// returns false if no process is ready
+    fn schedule(&mut self) -> bool {
+        self.slice_steps = 0;
+        let count = self.processes.len();
+        let first = self.current.map_or(0, |current| current + 1);
+        let next = match (0..count).map(|i| (first + i) % count).find(|i| self.processes[*i].is_alive()) {
+            Some(next) => next,
+            None => {
+                self.current = None;
+                return false;
+            }
+        };
+        if self.current != Some(next) {
+            if let Some(current) = self.current {
+                self.processes[current].regs = self.cpu.regs.clone();
+            }
+            self.cpu.regs = self.processes[next].regs.clone();
+            self.current = Some(next);
+        }
+        true
+    }
+
+    /// writes to stdout, & keeps a copy for the run's outcome
+    fn write_stdout(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.stdout_copy.extend_from_slice(bytes);
+        self.stdout.write_all(bytes)
+    }
+
+    /// updates the running process's peak stack depth & heap usage after an instruction
+    fn track_usage(&mut self) {
+        let heap = self.layout.heap();
+        let sp = self.cpu.regs.get(&Register::SP) as u32;
+        let last_write = self.cpu.mem.take_last_write();
+        let process = &mut self.processes[self.current.unwrap()];
+        process.peak_stack_depth = process.peak_stack_depth.max(process.initial_sp.saturating_sub(sp));
+        if let Some(addr) = last_write.filter(|addr| heap.contains(addr)) {
+            process.peak_heap_usage = process.peak_heap_usage.max(addr + 1 - heap.start);
+        }
+    }
+
     fn io_step(&mut self){
         if self.cpu.mem.get_num(COS) != 0 {
-            let c = self.cpu.mem.get_num(COD);
-            let c = c as u8 as char;
-            self.out_chars.push(c);
-            print!("{}", c);
+            let c = self.cpu.mem.get_num(COD) as u8;
+            self.write_stdout(&[c]).ok();
             // reset status register
             self.cpu.mem.set(COS, MemEntry::Num(0));
         }
         if self.cpu.mem.get_num(CIS) != 0 {
-            // read a single byte fron stdin
-            let mut input_handle = std::io::stdin().take(1);
+            // read a single byte from stdin, 0 at the end of the input
             let mut buffer = [0];
-            input_handle.read(&mut buffer);
-            let c = buffer[0] as char;
+            let c = match self.stdin.read(&mut buffer) {
+                Ok(1) => buffer[0],
+                _ => 0,
+            };
             self.cpu.mem.set(CID, MemEntry::Num(c as i32));
             self.cpu.mem.set(CIS, MemEntry::Num(0));
         }
+        if self.cpu.mem.get_num(YLD) != 0 {
+            self.yield_requested = true;
+            self.cpu.mem.set(YLD, MemEntry::Num(0));
+        }
     }
 
+    /// executes an instruction of the running process,
+    /// then switches to the next process if the running one exited, yielded or used up its time slice
+    /// returns whether any process is still running
     fn step(&mut self) -> bool {
-        let keep_running = self.cpu.step();
+        self.instructions += 1;
+        self.profile_instruction();
+        let keep_running = match self.cpu.step() {
+            Ok(keep_running) => keep_running,
+            Err(fault) => {
+                // the process is stopped, like a process that exited
+                let current = self.current.unwrap();
+                self.dump_core(&fault);
+                self.processes[current].state = ProcessState::Faulted(ProcessFault { fault, location: self.location(), backtrace: self.backtrace() });
+                self.processes[current].files.clear();
+                return self.schedule();
+            }
+        };
+        self.track_usage();
+        self.profile_flow();
         self.io_step();
-        keep_running
+        self.syscall_step();
+        self.slice_steps += 1;
+        let exit_status = if keep_running {
+            self.exit_requested.take()
+        } else {
+            // HALT after returning from main, whose return value is the exit status
+            let bp = self.cpu.regs.get(&Register::BP);
+            Some(self.cpu.mem.get_num((bp + 2) as u32))
+        };
+        if exit_status.is_none() && !self.yield_requested && self.slice_steps < self.quantum {
+            return true;
+        }
+        self.yield_requested = false;
+        if let (Some(status), Some(current)) = (exit_status, self.current) {
+            self.processes[current].state = ProcessState::Exited(status);
+            self.processes[current].files.clear();
+        }
+        self.schedule()
+    }
+
+    /// runs the loaded processes until all of them exit, or until a limit is exceeded
+    pub fn run_processes(&mut self) -> Result<(), LimitExceeded> {
+        if self.current.is_none() && !self.schedule() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        loop {
+            if let Some(limit) = self.exceeded_limit(start) {
+                return Err(LimitExceeded { limit, location: self.location() });
+            }
+            if !self.step() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// the name of the running process's function at addr
+    fn function_name(&self, addr: u32) -> String {
+        let process = &self.processes[self.current.unwrap()];
+        process.function_at(addr).map_or(UNKNOWN_FUNCTION.to_string(), |(name, _)| name)
+    }
+
+    /// counts the running process's next instruction in the profile, if there is one
+    fn profile_instruction(&mut self) {
+        if self.profile.is_none() {
+            return;
+        }
+        let pid = self.processes[self.current.unwrap()].pid;
+        let function = self.function_name(self.cpu.regs.get(&Register::IR) as u32);
+        self.profile.as_mut().unwrap().instruction(pid, &function);
     }
 
-    fn run(&mut self){
-        loop{
-            let keep_running = self.step();
-            if !keep_running {
-                break;
+    /// follows the CALL or RET the running process executed in the profile, if there is one
+    fn profile_flow(&mut self) {
+        let event = self.cpu.take_flow_event();
+        if self.profile.is_none() {
+            return;
+        }
+        let pid = self.processes[self.current.unwrap()].pid;
+        match event {
+            Some(FlowEvent::Call(callee)) => {
+                let callee = self.function_name(callee);
+                self.profile.as_mut().unwrap().call(pid, &callee);
             }
+            Some(FlowEvent::Return) => self.profile.as_mut().unwrap().ret(pid),
+            None => {}
+        }
+    }
+
+    /// writes the running process's core file to the core directory, if there is one
+    fn dump_core(&self, fault: &Fault) {
+        let dir = match &self.core_dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        let process = &self.processes[self.current.unwrap()];
+        let core = CoreDump {
+            pid: process.pid,
+            fault: fault.clone(),
+            cpu: self.cpu.clone(),
+            code: process.code.clone(),
+            symbols: process.symbols.clone(),
+            data_symbols: process.data_symbols.clone(),
+            stdout: self.stdout_copy.clone(),
+        };
+        let path = dir.join(format!("core.{}", process.pid));
+        match save_core(&core, &path.to_string_lossy()) {
+            Ok(()) => eprintln!("wrote core file: {}", path.display()),
+            Err(e) => eprintln!("failed to write {}: {}", path.display(), e),
+        }
+    }
+
+    fn exceeded_limit(&self, start: Instant) -> Option<Limit> {
+        match (self.limits.max_instructions, self.limits.timeout) {
+            (Some(max), _) if self.instructions >= max => Some(Limit::Instructions(max)),
+            (_, Some(timeout)) if start.elapsed() >= timeout => Some(Limit::Timeout(timeout)),
+            _ => None,
+        }
+    }
+
+    /// where the running process is
+    fn location(&self) -> Location {
+        let process = &self.processes[self.current.unwrap()];
+        let addr = self.cpu.regs.get(&Register::IR) as u32;
+        Location { pid: process.pid, addr, function: process.function_at(addr) }
+    }
+
+    /// the frames of the running process's stack
+    pub fn backtrace(&self) -> Vec<Frame> {
+        let process = &self.processes[self.current.unwrap()];
+        backtrace(&self.cpu.regs, &self.cpu.mem, |addr| process.function_at(addr))
+    }
+
+    /// how the process ended, it mustn't be running
+    fn final_status(&self, pid: Pid) -> RunStatus {
+        match &self.processes.iter().find(|process| process.pid == pid).unwrap().state {
+            ProcessState::Faulted(fault) => RunStatus::Fault(fault.clone()),
+            _ => RunStatus::Exited(self.exit_status(pid).unwrap()),
         }
     }
 
     // runs given program
     // returns program's exit value
     pub fn load_and_run(&mut self, exec: &Executable) -> i32 {
-        self.reset_cpu_state();
-        self.load_program(&exec.code, &exec.data);
-        self.cpu
-            .regs
-            .set(&Register::IR, PROGRAM_INIT_ADDRESS as i32);
-        self.initialize_stackframe();
-        self.run();
+        self.load_and_run_at(exec, self.layout.code().start, self.layout.data().start)
+    }
 
-        let bp = self.cpu.regs.get(&Register::BP);
-        self.cpu.mem.get_num((bp + 2) as u32)
+    /// like load_and_run, with the program's code & data placed at the given addresses
+    /// panics if the program faults or exceeds a limit
+    pub fn load_and_run_at(&mut self, exec: &Executable, code_base: u32, data_base: u32) -> i32 {
+        let outcome = self.run_at(exec, code_base, data_base);
+        outcome.exit_status().unwrap_or_else(|| panic!("the program didn't exit, {}", outcome.status))
+    }
+
+    /// runs the program within the OS's limits
+    pub fn run(&mut self, exec: &Executable) -> RunOutcome {
+        self.run_at(exec, self.layout.code().start, self.layout.data().start)
+    }
+
+    /// the program runs as the only process, with the whole stack region
+    /// panics if the program doesn't fit the memory layout
+    fn run_at(&mut self, exec: &Executable, code_base: u32, data_base: u32) -> RunOutcome {
+        self.reset_cpu_state();
+        let pid = self.create_process(exec, code_base, data_base, self.layout.stack()).unwrap_or_else(|e| panic!("failed to load the program: {}", e));
+        let status = match self.run_processes() {
+            Err(e) => RunStatus::LimitExceeded(e),
+            Ok(()) => self.final_status(pid),
+        };
+        let process = self.processes.iter().find(|process| process.pid == pid).unwrap();
+        RunOutcome {
+            status,
+            stdout: std::mem::take(&mut self.stdout_copy),
+            instructions: self.instructions,
+            peak_stack_depth: process.peak_stack_depth,
+            peak_heap_usage: process.peak_heap_usage,
+        }
     }
 
     pub fn assemble_link_and_run(&mut self, programs: Vec<&str>) -> i32 {
-        let mut programs_with_std = programs;
-        let mut std_programs_clone = self.std_programs.iter().map(|s| s.as_str()).collect();
-        programs_with_std.append(&mut std_programs_clone);
-        let exec = assemble_and_link(programs_with_std);
+        let exec = exec_or_panic(self.link_with_std(programs));
         self.load_and_run(&exec)
     }
 
@@ -149,24 +523,23 @@ impl OS {
     }
 
     pub fn assemble_and_run_no_std(&mut self, program: &str) -> i32{
-        let exec = assemble_and_link(vec![program]);
+        let exec = exec_or_panic(assemble_and_link(vec![program]));
         self.load_and_run(&exec)
     }
 
-    pub fn debug_program(&mut self, exec: &Executable) -> i32{
+    /// runs the program under the debugger, with commands read from stdin
+    pub fn debug_program(&mut self, exec: &Executable) -> RunStatus {
         self.reset_cpu_state();
-        self.load_program(&exec.code, &exec.data);
-        self.cpu
-            .regs
-            .set(&Register::IR, PROGRAM_INIT_ADDRESS as i32);
-        self.initialize_stackframe();
+        let code_base = self.layout.code().start;
+        let pid = self.create_process(exec, code_base, self.layout.data().start, self.layout.stack()).unwrap_or_else(|e| panic!("failed to load the program: {}", e));
+        self.schedule();
         let mut breakpoints : HashSet<u32> = HashSet::new();
         let mut running = false;
         let mut keep_running = true;
         while keep_running{
             let cur_instr_addr = self.cpu.regs.get(&Register::IR);
             // println!("{}: {}", cur_instr_addr - PROGRAM_INIT_ADDRESS as i32, self.cpu.fetch().to_str());
-            if breakpoints.contains(&(cur_instr_addr as u32 - PROGRAM_INIT_ADDRESS)){
+            if breakpoints.contains(&(cur_instr_addr as u32 - code_base)){
                 running = false;
             }
             if running{
@@ -174,8 +547,8 @@ impl OS {
                 continue;
             }
             let next_instr = self.cpu.fetch();
-            println!("{}: {}", self.cpu.regs.get(&Register::IR) - PROGRAM_INIT_ADDRESS as i32, next_instr.to_str());
-            use std::io::{stdin,stdout,Write};
+            println!("{}: {}", self.cpu.regs.get(&Register::IR) - code_base as i32, next_instr.to_str());
+            use std::io::stdin;
             let mut cmd = String::new();
             if let Some('\n')=cmd.chars().next_back() {
                 cmd.pop();
@@ -189,16 +562,19 @@ impl OS {
                 running = true;
             }
             if args[0] == "step"{
-                keep_running = self.cpu.step();
+                keep_running = self.step();
             }
             if args[0] == "reg"{
                 let reg = register_from_str(args[1]).unwrap();
                 let reg_val = self.cpu.regs.get(&reg);
                 println!("{}", reg_val);
             }
+            if args[0] == "bt"{
+                print!("{}", format_backtrace(&self.backtrace()));
+            }
             if args[0] == "break"{
                 let line = args[1];
-                let instr_i = exec.symbol_table.get(&format!("_LINE_{}", line)).expect("invalid breakpoint line");
+                let instr_i = exec.symbol_table.get(&format!("{}{}", LINE_SYMBOL_PREFIX, line)).expect("invalid breakpoint line");
                 println!("break instr: {:?}", &exec.code[*instr_i as usize]);
                 breakpoints.insert(*instr_i);
 
@@ -206,22 +582,16 @@ impl OS {
             
         }
 
-        let bp = self.cpu.regs.get(&Register::BP);
-        self.cpu.mem.get_num((bp + 2) as u32)
+        self.final_status(pid)
     }
 
-    pub fn assemble_and_debug(&mut self, programs: Vec<&str>) -> i32 {
-        let mut programs_with_std = programs;
-        let mut std_programs_clone = self.std_programs.iter().map(|s| s.as_str()).collect();
-        programs_with_std.append(&mut std_programs_clone);
-        let exec = assemble_and_link(programs_with_std);
+    pub fn assemble_and_debug(&mut self, programs: Vec<&str>) -> RunStatus {
+        let exec = exec_or_panic(self.link_with_std(programs));
         self.debug_program(&exec)
     }
 
-    pub fn compile(&mut self, path_to_c_source: &str) -> String{
-        let res = Compiler::compile(path_to_c_source, self.compiled_programs_count);
-        self.compiled_programs_count += 1;
-        res
+    pub fn compile(&self, path_to_c_source: &str) -> String{
+        Compiler::compile_with_options(path_to_c_source, &self.compile_options)
     }
 
 }
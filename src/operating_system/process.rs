@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::ops::Range;
+
+use super::run::ProcessFault;
+use crate::cpu::Registers;
+
+/*
+Processes share the VM's memory, each one is loaded into its own part of the code, data & stack regions.
+The registers of the running process are the CPU's, the registers of the others are saved in their Process.
+The scheduler is round-robin: the running process is switched when it HALTs, yields or its time slice ends.
+A process yields by writing 1 to the YLD memory mapped register.
+The heap region isn't split between processes, sbrk fails while another live process has moved its break,
+so only one of the processes that run together gets memory from malloc, it returns NULL for the others.
+*/
+
+pub type Pid = u32;
+
+pub const DEFAULT_QUANTUM: u32 = 100; // # of instructions in a time slice
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ProcessState {
+    Ready,
+    Exited(i32), // exit status, the return value of main
+    Faulted(ProcessFault),
+}
+
+pub struct Process {
+    pub pid: Pid,
+    pub state: ProcessState,
+    pub code: Range<u32>, // addresses the process is loaded at
+    pub data: Range<u32>,
+    pub stack: Range<u32>,
+    pub brk: u32, // end of the process's heap, moved by sbrk
+    pub peak_stack_depth: u32, // # of words below the initial SP the stack reached
+    pub peak_heap_usage: u32, // # of words from the heap's start to the highest heap address the process wrote
+    pub(super) initial_sp: u32,
+    pub(super) files: HashMap<i32, File>, // open files by file descriptor, 0-2 are the standard streams
+    pub(super) symbols: Vec<(u32, String)>, // the executable's code symbols at their loaded addresses, sorted
+    pub(super) data_symbols: Vec<(u32, String)>, // the executable's data symbols at their loaded addresses, sorted
+    pub(super) regs: Registers, // saved while the process isn't running
+}
+
+impl Process {
+    pub fn is_alive(&self) -> bool {
+        self.state == ProcessState::Ready
+    }
+
+    pub fn function_at(&self, addr: u32) -> Option<(String, u32)> {
+        function_at(&self.symbols, &self.code, addr)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    NoRoom { segment: &'static str, size: u32 }, // no free part of the segment's region is big enough
+    Overflow { segment: &'static str, size: u32, available: u32 }, // the segment overflows its region at the address it's loaded at
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::NoRoom { segment, size } => write!(f, "no room for {} words of {}", size, segment),
+            LoadError::Overflow { segment, size, available } => write!(f, "{} words of {} overflow its region, which has room for {}", size, segment, available),
+        }
+    }
+}
+
+/// the symbol at or before addr & the offset of addr from it, symbols are sorted by address
+pub fn function_at(symbols: &[(u32, String)], code: &Range<u32>, addr: u32) -> Option<(String, u32)> {
+    if !code.contains(&addr) {
+        return None;
+    }
+    let i = symbols.partition_point(|(symbol_addr, _)| *symbol_addr <= addr).checked_sub(1)?;
+    let (symbol_addr, name) = &symbols[i];
+    Some((name.clone(), addr - symbol_addr))
+}
+
+/// the symbols of a table at their loaded addresses, sorted by address
+pub fn loaded_symbols<'a>(table: impl Iterator<Item = (&'a String, &'a u32)>, base: u32) -> Vec<(u32, String)> {
+    let mut symbols: Vec<(u32, String)> = table.map(|(name, addr)| (base + addr, name.clone())).collect();
+    symbols.sort();
+    symbols
+}
+
+/// checks that size words loaded at base fit region
+pub fn check_fits(region: &Range<u32>, base: u32, size: u32, segment: &'static str) -> Result<(), LoadError> {
+    let available = if region.contains(&base) { region.end - base } else { 0 };
+    if size > available {
+        return Err(LoadError::Overflow { segment, size, available });
+    }
+    Ok(())
+}
+
+/// the lowest address of region where size words fit without overlapping used, which are sorted
+pub fn first_fit(region: &Range<u32>, used: &[Range<u32>], size: u32) -> Option<u32> {
+    let mut start = region.start;
+    for range in used.iter() {
+        if range.start >= start + size {
+            break;
+        }
+        start = start.max(range.end);
+    }
+    if start + size <= region.end { Some(start) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_first_fit() {
+        let region = 100..200;
+        assert_eq!(first_fit(&region, &[], 100), Some(100));
+        assert_eq!(first_fit(&region, &[], 101), None);
+        let used = vec![100..110, 120..150];
+        assert_eq!(first_fit(&region, &used, 10), Some(110));
+        assert_eq!(first_fit(&region, &used, 11), Some(150));
+        assert_eq!(first_fit(&region, &used, 51), None);
+        assert_eq!(first_fit(&region, &[150..200], 50), Some(100));
+    }
+
+    #[test]
+    fn test_check_fits() {
+        let region = 100..200;
+        assert_eq!(check_fits(&region, 100, 100, "code"), Ok(()));
+        assert_eq!(check_fits(&region, 150, 0, "code"), Ok(()));
+        assert_eq!(check_fits(&region, 150, 51, "code"), Err(LoadError::Overflow { segment: "code", size: 51, available: 50 }));
+        assert_eq!(check_fits(&region, 50, 1, "data"), Err(LoadError::Overflow { segment: "data", size: 1, available: 0 }));
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use super::process::Pid;
+
+/*
+A function-level profile of the executed instructions, collected by the OS while its profile is set.
+Every process has a path in a call tree, a CALL adds the callee under the running function & a RET goes back to the caller.
+An instruction is counted in the function whose code it's in, so a jump to another function, e.g to main at the entry point,
+replaces the running function instead of nesting under it.
+A function's self count is of its own instructions, its inclusive count also has those of the functions it called.
+*/
+
+pub const UNKNOWN_FUNCTION: &str = "??"; // an address before any code symbol
+
+struct Node {
+    function: String,
+    parent: usize,
+    children: HashMap<String, usize>,
+    instructions: u64, // executed with this path as the stack
+}
+
+pub struct Profile {
+    nodes: Vec<Node>, // the call tree, nodes[0] is the root, the bottom of every stack
+    running: HashMap<Pid, usize>, // node of each process's running function
+    calls: HashMap<(String, String), u64>, // # of calls by caller & callee
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    pub function: String,
+    pub self_instructions: u64,
+    pub inclusive_instructions: u64,
+    pub calls: u64, // # of times it was called
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::new()
+    }
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        let root = Node { function: String::new(), parent: 0, children: HashMap::new(), instructions: 0 };
+        Profile { nodes: vec![root], running: HashMap::new(), calls: HashMap::new() }
+    }
+
+    fn child(&mut self, parent: usize, function: &str) -> usize {
+        if let Some(child) = self.nodes[parent].children.get(function) {
+            return *child;
+        }
+        let child = self.nodes.len();
+        self.nodes.push(Node { function: function.to_string(), parent, children: HashMap::new(), instructions: 0 });
+        self.nodes[parent].children.insert(function.to_string(), child);
+        child
+    }
+
+    /// counts an instruction of the process, which is in the given function
+    pub fn instruction(&mut self, pid: Pid, function: &str) {
+        let mut node = *self.running.get(&pid).unwrap_or(&0);
+        if node == 0 || self.nodes[node].function != function {
+            node = self.child(self.nodes[node].parent, function);
+            self.running.insert(pid, node);
+        }
+        self.nodes[node].instructions += 1;
+    }
+
+    /// the process's running function called the given one
+    pub fn call(&mut self, pid: Pid, callee: &str) {
+        let caller = *self.running.get(&pid).unwrap_or(&0);
+        *self.calls.entry((self.nodes[caller].function.clone(), callee.to_string())).or_insert(0) += 1;
+        let node = self.child(caller, callee);
+        self.running.insert(pid, node);
+    }
+
+    /// the process's running function returned to its caller
+    pub fn ret(&mut self, pid: Pid) {
+        if let Some(node) = self.running.get_mut(&pid) {
+            if self.nodes[*node].parent != 0 {
+                *node = self.nodes[*node].parent;
+            }
+        }
+    }
+
+    /// the functions of the stack from the bottom, node included
+    fn stack(&self, mut node: usize) -> Vec<&str> {
+        let mut stack = Vec::new();
+        while node != 0 {
+            stack.push(self.nodes[node].function.as_str());
+            node = self.nodes[node].parent;
+        }
+        stack.reverse();
+        stack
+    }
+
+    /// the profile of every function that executed instructions or was called, by decreasing self count
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions: HashMap<&str, FunctionProfile> = HashMap::new();
+        for (node_i, node) in self.nodes.iter().enumerate().skip(1) {
+            function_entry(&mut functions, &node.function).self_instructions += node.instructions;
+            // a recursive function's instructions count once in its inclusive count
+            let stack: HashSet<&str> = self.stack(node_i).into_iter().collect();
+            for function in stack {
+                function_entry(&mut functions, function).inclusive_instructions += node.instructions;
+            }
+        }
+        for ((_, callee), count) in self.calls.iter() {
+            function_entry(&mut functions, callee).calls += count;
+        }
+        let mut functions: Vec<FunctionProfile> = functions.into_values().collect();
+        functions.sort_by(|a, b| b.self_instructions.cmp(&a.self_instructions).then(a.function.cmp(&b.function)));
+        functions
+    }
+
+    /// # of calls by caller & callee, sorted by caller & callee
+    pub fn call_counts(&self) -> Vec<(String, String, u64)> {
+        let mut calls: Vec<(String, String, u64)> = self.calls.iter().map(|((caller, callee), count)| (caller.clone(), callee.clone(), *count)).collect();
+        calls.sort();
+        calls
+    }
+
+    /// a table of the functions, then of the calls between them
+    pub fn flat_profile(&self) -> String {
+        let functions = self.functions();
+        let total: u64 = functions.iter().map(|function| function.self_instructions).sum();
+        let mut out = String::new();
+        writeln!(out, "{:>12} {:>7} {:>12} {:>8}  function", "self", "self %", "inclusive", "calls").unwrap();
+        for function in functions.iter() {
+            let percent = 100.0 * function.self_instructions as f64 / total.max(1) as f64;
+            writeln!(out, "{:>12} {:>6.2}% {:>12} {:>8}  {}", function.self_instructions, percent, function.inclusive_instructions, function.calls, function.function).unwrap();
+        }
+        writeln!(out, "\n{:>8}  caller -> callee", "calls").unwrap();
+        for (caller, callee, count) in self.call_counts() {
+            writeln!(out, "{:>8}  {} -> {}", count, caller, callee).unwrap();
+        }
+        out
+    }
+
+    /// one "bottom;...;top count" line per stack that executed instructions, the folded stacks format of flame graph tools
+    pub fn folded_stacks(&self) -> String {
+        let mut lines: Vec<String> = (1..self.nodes.len())
+            .filter(|node_i| self.nodes[*node_i].instructions > 0)
+            .map(|node_i| format!("{} {}\n", self.stack(node_i).join(";"), self.nodes[node_i].instructions))
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+fn function_entry<'a, 'b>(functions: &'b mut HashMap<&'a str, FunctionProfile>, function: &'a str) -> &'b mut FunctionProfile {
+    functions.entry(function).or_insert_with(|| FunctionProfile {
+        function: function.to_string(), self_instructions: 0, inclusive_instructions: 0, calls: 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_profile() {
+        let mut profile = Profile::new();
+        // entry code jumps to main, which calls f twice, f calls itself once
+        profile.instruction(1, UNKNOWN_FUNCTION);
+        profile.instruction(1, "main");
+        for _ in 0..2 {
+            profile.instruction(1, "main");
+            profile.call(1, "f");
+            profile.instruction(1, "f");
+            profile.call(1, "f");
+            profile.instruction(1, "f");
+            profile.ret(1);
+            profile.instruction(1, "f");
+            profile.ret(1);
+        }
+        profile.instruction(1, "main");
+        // another process's stack is apart
+        profile.instruction(2, "main");
+
+        assert_eq!(profile.functions(), vec![
+            FunctionProfile { function: "f".to_string(), self_instructions: 6, inclusive_instructions: 6, calls: 4 },
+            FunctionProfile { function: "main".to_string(), self_instructions: 5, inclusive_instructions: 11, calls: 0 },
+            FunctionProfile { function: UNKNOWN_FUNCTION.to_string(), self_instructions: 1, inclusive_instructions: 1, calls: 0 },
+        ]);
+        assert_eq!(profile.call_counts(), vec![("f".to_string(), "f".to_string(), 2), ("main".to_string(), "f".to_string(), 2)]);
+        assert_eq!(profile.folded_stacks(), "?? 1\nmain 5\nmain;f 4\nmain;f;f 2\n");
+        // a RET without a CALL, e.g of main, stays in the function
+        profile.ret(2);
+        profile.instruction(2, "main");
+        assert_eq!(profile.folded_stacks(), "?? 1\nmain 6\nmain;f 4\nmain;f;f 2\n");
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::backtrace::Frame;
+use super::process::Pid;
+use crate::cpu::Fault;
+
+/*
+Limits on a run & how it ended.
+A run that hits a limit is stopped, the outcome tells which limit & where the running process was.
+A process whose instruction faults is stopped, the other processes keep running.
+*/
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunLimits {
+    pub max_instructions: Option<u64>, // # of instructions all of the processes execute together
+    pub timeout: Option<Duration>, // wall-clock time
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Instructions(u64),
+    Timeout(Duration),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions(max) => write!(f, "instruction limit of {}", max),
+            Limit::Timeout(timeout) => write!(f, "timeout of {:?}", timeout),
+        }
+    }
+}
+
+/// where a process was, the function is the code symbol at or before the address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub pid: Pid,
+    pub addr: u32,
+    pub function: Option<(String, u32)>, // name & offset of the address in it
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((name, offset)) = &self.function {
+            write!(f, "{}+{} ", name, offset)?;
+        }
+        write!(f, "(address {}) in process {}", self.addr, self.pid)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    pub location: Location,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} exceeded at {}", self.limit, self.location)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessFault {
+    pub fault: Fault,
+    pub location: Location, // of the faulting instruction
+    pub backtrace: Vec<Frame>, // from the faulting instruction to main
+}
+
+impl fmt::Display for ProcessFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.fault, self.location)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunStatus {
+    Exited(i32),
+    Fault(ProcessFault),
+    LimitExceeded(LimitExceeded),
+}
+
+impl fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunStatus::Exited(status) => write!(f, "exited with {}", status),
+            RunStatus::Fault(fault) => write!(f, "fault: {}", fault),
+            RunStatus::LimitExceeded(e) => write!(f, "stopped: {}", e),
+        }
+    }
+}
+
+/// how a run of a program went
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOutcome {
+    pub status: RunStatus,
+    pub stdout: Vec<u8>, // everything the program wrote to stdout, whatever stdout is connected to
+    pub instructions: u64, // # of executed instructions
+    pub peak_stack_depth: u32, // in words, below the initial stack frame
+    pub peak_heap_usage: u32, // in words, from the heap's start to the highest heap address written
+}
+
+impl RunOutcome {
+    /// the exit status, None if the program didn't exit
+    pub fn exit_status(&self) -> Option<i32> {
+        match self.status {
+            RunStatus::Exited(status) => Some(status),
+            _ => None,
+        }
+    }
+}
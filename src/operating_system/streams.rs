@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io;
+use std::io::{Cursor, Read, Write};
+
+/*
+The streams a program's standard input, output & error are connected to.
+Programs read & write bytes, which pass through as is, e.g the UTF-8 bytes of a character.
+*/
+
+pub enum Input {
+    Terminal, // the host's stdin
+    Buffer(Cursor<Vec<u8>>),
+    File(File),
+}
+
+pub enum Output {
+    Terminal, // the host's stdout
+    TerminalErr, // the host's stderr
+    Buffer(Vec<u8>),
+    File(File),
+}
+
+impl Input {
+    /// a scripted input of the given bytes, after them the input ends
+    pub fn buffer(bytes: impl Into<Vec<u8>>) -> Input {
+        Input::Buffer(Cursor::new(bytes.into()))
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Terminal => io::stdin().read(buf),
+            Input::Buffer(cursor) => cursor.read(buf),
+            Input::File(file) => file.read(buf),
+        }
+    }
+}
+
+impl Output {
+    pub fn buffer() -> Output {
+        Output::Buffer(Vec::new())
+    }
+
+    /// the bytes written to a buffer, None for the other outputs
+    pub fn contents(&self) -> Option<&[u8]> {
+        match self {
+            Output::Buffer(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Terminal => io::stdout().write(buf),
+            Output::TerminalErr => io::stderr().write(buf),
+            Output::Buffer(bytes) => bytes.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Terminal => io::stdout().flush(),
+            Output::TerminalErr => io::stderr().flush(),
+            Output::Buffer(_) => Ok(()),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_streams() {
+        let mut input = Input::buffer("é!");
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, "é!".as_bytes());
+        assert_eq!(input.read(&mut [0]).unwrap(), 0);
+        let mut output = Output::buffer();
+        output.write_all(&bytes).unwrap();
+        assert_eq!(output.contents(), Some("é!".as_bytes()));
+        assert_eq!(Output::Terminal.contents(), None);
+    }
+}
@@ -0,0 +1,218 @@
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::ops::Range;
+
+use super::filesystem::*;
+use super::layout::*;
+use super::OS;
+use crate::cpu::MemEntry;
+
+/*
+System calls:
+a program writes the arguments to SA1-SA3 & then the number of the call to SYS,
+the OS performs the call right after that instruction & writes its result to SRV.
+    1 exit(status): ends the process with the given exit status
+    2 read(fd, buf, n): reads up to n chars from fd (0 is stdin) into buf, returns the # of chars read, 0 at the end of the input
+    3 write(fd, buf, n): writes n chars from buf to fd (1 is stdout, 2 is stderr), returns n
+    4 sbrk(n): moves the end of the process's heap by n words, returns the previous end,
+      fails while another live process has moved its own, the processes share the heap region
+    5 open(path, flags): opens a file of the OS's filesystem, flags are POSIX's O_* flags, returns its file descriptor
+    6 close(fd): closes a file, returns 0
+    7 lseek(fd, offset, whence): moves the position in a file, whence is SEEK_SET, SEEK_CUR or SEEK_END, returns the new position
+    8 unlink(path): deletes a file, returns 0
+Paths are zero terminated strings. A call that fails returns -1, e.g when a buffer or path isn't readable memory.
+read writes only into the data, heap & stack regions, up to the end of buf's region.
+The files of a process are closed when it exits.
+*/
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Syscall {
+    Exit = 1,
+    Read = 2,
+    Write = 3,
+    Sbrk = 4,
+    Open = 5,
+    Close = 6,
+    Lseek = 7,
+    Unlink = 8,
+}
+
+impl Syscall {
+    pub fn from_num(num: i32) -> Option<Syscall> {
+        match num {
+            1 => Some(Syscall::Exit),
+            2 => Some(Syscall::Read),
+            3 => Some(Syscall::Write),
+            4 => Some(Syscall::Sbrk),
+            5 => Some(Syscall::Open),
+            6 => Some(Syscall::Close),
+            7 => Some(Syscall::Lseek),
+            8 => Some(Syscall::Unlink),
+            _ => None,
+        }
+    }
+}
+
+impl OS {
+    /// performs the system call requested by the last instruction, if any
+    pub(super) fn syscall_step(&mut self) {
+        let num = self.cpu.mem.get_num(SYS);
+        if num == 0 {
+            return;
+        }
+        self.cpu.mem.set(SYS, MemEntry::Num(0));
+        let [arg1, arg2, arg3] = [SA1, SA2, SA3].map(|addr| self.cpu.mem.get_num(addr));
+        let res = match Syscall::from_num(num) {
+            Some(Syscall::Exit) => {
+                self.exit_requested = Some(arg1);
+                0
+            }
+            Some(Syscall::Read) => self.sys_read(arg1, arg2 as u32, arg3),
+            Some(Syscall::Write) => self.sys_write(arg1, arg2 as u32, arg3),
+            Some(Syscall::Sbrk) => self.sys_sbrk(arg1),
+            Some(Syscall::Open) => self.sys_open(arg1 as u32, arg2),
+            Some(Syscall::Close) => self.sys_close(arg1),
+            Some(Syscall::Lseek) => self.sys_lseek(arg1, arg2, arg3),
+            Some(Syscall::Unlink) => self.sys_unlink(arg1 as u32),
+            None => -1,
+        };
+        self.cpu.mem.set(SRV, MemEntry::Num(res));
+    }
+
+    /// the open file of the running process with the given descriptor
+    fn file(&mut self, fd: i32) -> Option<&mut std::fs::File> {
+        self.processes[self.current?].files.get_mut(&fd)
+    }
+
+    /// the zero terminated string at addr, None if it isn't readable
+    fn read_string(&self, addr: u32) -> Option<String> {
+        let mut string = String::new();
+        for addr in addr.. {
+            match self.cpu.mem.try_get_num(addr).ok()? {
+                0 => return Some(string),
+                c => string.push(c as u8 as char),
+            }
+        }
+        None
+    }
+
+    /// the region of the memory layout that programs may write & that contains addr
+    fn writable_region(&self, addr: u32) -> Option<Range<u32>> {
+        [self.layout.data(), self.layout.heap(), self.layout.stack()].iter().find(|region| region.contains(&addr)).cloned()
+    }
+
+    fn sys_read(&mut self, fd: i32, buf: u32, n: i32) -> i32 {
+        let region = match self.writable_region(buf) {
+            Some(region) if n >= 0 => region,
+            _ => return -1,
+        };
+        let mut buffer = vec![0; (n as u32).min(region.end - buf) as usize];
+        let res = match fd {
+            0 => self.stdin.read(&mut buffer),
+            _ => match self.file(fd) {
+                Some(file) => file.read(&mut buffer),
+                None => return -1,
+            },
+        };
+        let count = match res {
+            Ok(count) => count,
+            Err(_) => return -1,
+        };
+        for (i, c) in buffer[..count].iter().enumerate() {
+            self.cpu.mem.set(buf + i as u32, MemEntry::Num(*c as i32));
+        }
+        count as i32
+    }
+
+    fn sys_write(&mut self, fd: i32, buf: u32, n: i32) -> i32 {
+        if n < 0 {
+            return -1;
+        }
+        let bytes: Vec<u8> = match (0..n as u32).map(|i| self.cpu.mem.try_get_num(buf.wrapping_add(i)).map(|c| c as u8)).collect() {
+            Ok(bytes) => bytes,
+            Err(_) => return -1,
+        };
+        let res = match fd {
+            1 => self.write_stdout(&bytes).and_then(|_| self.stdout.flush()),
+            2 => self.stderr.write_all(&bytes),
+            _ => match self.file(fd) {
+                Some(file) => file.write_all(&bytes),
+                None => return -1,
+            },
+        };
+        if res.is_err() {
+            return -1;
+        }
+        n
+    }
+
+    fn sys_sbrk(&mut self, n: i32) -> i32 {
+        let heap = self.layout.heap();
+        let current = match self.current {
+            Some(current) => current,
+            None => return -1,
+        };
+        // the heap isn't split, it's owned by the live process that moved its break
+        let owned_by_other = self.processes.iter().enumerate().any(|(i, process)| i != current && process.is_alive() && process.brk != heap.start);
+        if owned_by_other {
+            return -1;
+        }
+        let process = &mut self.processes[current];
+        let brk = process.brk as i32;
+        match brk.checked_add(n) {
+            Some(new_brk) if (heap.start as i32..=heap.end as i32).contains(&new_brk) => process.brk = new_brk as u32,
+            _ => return -1,
+        }
+        brk
+    }
+
+    fn sys_open(&mut self, path: u32, flags: i32) -> i32 {
+        let path = self.read_string(path);
+        let (fs, current, path) = match (&self.fs, self.current, path) {
+            (Some(fs), Some(current), Some(path)) => (fs, current, path),
+            _ => return -1,
+        };
+        let file = match fs.open(&path, flags) {
+            Ok(file) => file,
+            Err(_) => return -1,
+        };
+        let files = &mut self.processes[current].files;
+        // the lowest free descriptor, as in POSIX
+        let fd = (3..).find(|fd| !files.contains_key(fd)).unwrap();
+        files.insert(fd, file);
+        fd
+    }
+
+    fn sys_close(&mut self, fd: i32) -> i32 {
+        match self.current.and_then(|current| self.processes[current].files.remove(&fd)) {
+            Some(_) => 0,
+            None => -1,
+        }
+    }
+
+    fn sys_lseek(&mut self, fd: i32, offset: i32, whence: i32) -> i32 {
+        let pos = match whence {
+            SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+            SEEK_CUR => SeekFrom::Current(offset as i64),
+            SEEK_END => SeekFrom::End(offset as i64),
+            _ => return -1,
+        };
+        match self.file(fd).map(|file| file.seek(pos)) {
+            Some(Ok(new_pos)) => new_pos as i32,
+            _ => -1,
+        }
+    }
+
+    fn sys_unlink(&mut self, path: u32) -> i32 {
+        let path = match self.read_string(path) {
+            Some(path) => path,
+            None => return -1,
+        };
+        match self.fs.as_ref().map(|fs| fs.unlink(&path)) {
+            Some(Ok(())) => 0,
+            _ => -1,
+        }
+    }
+}
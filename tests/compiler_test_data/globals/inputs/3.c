@@ -0,0 +1,17 @@
+struct point {
+    int x;
+    int y;
+};
+
+int counter = 5;
+int table[2][3] = {{1, 2}, {3, 4, 5}};
+struct point origin = {-1, 'a' - 90};
+char* greeting = "hi";
+int primes[4] = {2, 3, 5};
+int* third = primes + 2;
+int flags = (1 << 4) | sizeof(struct point);
+
+int main(){
+    counter += 1;
+    return counter + table[0][1] + table[1][2] + table[0][2] + origin.x + origin.y + *(greeting + 1) + *third + primes[3] + flags;
+}
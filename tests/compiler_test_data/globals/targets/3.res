@@ -0,0 +1 @@
+147
\ No newline at end of file
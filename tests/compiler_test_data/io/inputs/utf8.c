@@ -0,0 +1,10 @@
+#include <libc.h>
+
+int main(){
+    // the UTF-8 bytes of é pass through as is
+    putc(195);
+    putc(169);
+    char buf[3];
+    write(1, "\xc3\xa8!", 3);
+    return read(0, buf, 3);
+}
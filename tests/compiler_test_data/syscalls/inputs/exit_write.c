@@ -0,0 +1,22 @@
+#include <libc.h>
+
+void finish(int status){
+    write(1, "bye", 3);
+    exit(status);
+    write(1, "unreachable", 11);
+}
+
+int main(){
+    char* heap = sbrk(3);
+    char* end = sbrk(0);
+    char* c = heap;
+    *c = 'o';
+    c++;
+    *c = 'k';
+    c++;
+    *c = ' ';
+    write(1, heap, 3);
+    // writing to a file descriptor that isn't open fails
+    finish(end - heap + 40 + write(7, heap, 3));
+    return 1;
+}
@@ -1,7 +1,16 @@
 extern crate simple_vm;
 
 use simple_vm::cpu::instructions::Register;
-use simple_vm::operating_system::OS;
+use simple_vm::cpu::Fault;
+use simple_vm::operating_system::assembler::assemble_and_link;
+use simple_vm::operating_system::backtrace::format_backtrace;
+use simple_vm::operating_system::exec_file::load_core;
+use simple_vm::operating_system::layout::MemoryLayout;
+use simple_vm::operating_system::process::{LoadError, ProcessState};
+use simple_vm::operating_system::profiler::Profile;
+use simple_vm::operating_system::run::{Limit, RunLimits, RunStatus};
+use simple_vm::operating_system::streams::{Input, Output};
+use simple_vm::operating_system::{OsConfig, OS};
 
 #[test]
 fn test_add() {
@@ -11,7 +20,7 @@ fn test_add() {
     ADD R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let res = os.assemble_and_run_no_std(program);
     assert_eq!(res, -1);
     assert_eq!(os.cpu.regs.get(&Register::R1), 3);
@@ -26,7 +35,7 @@ fn test_sub() {
     SUB R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 1);
 }
@@ -39,7 +48,7 @@ fn test_mul() {
     MUL R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 6);
 }
@@ -51,7 +60,7 @@ fn test_div() {
     DIV R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 2);
 }
@@ -63,7 +72,7 @@ fn test_mod() {
     MOD R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 1);
 }
@@ -75,7 +84,7 @@ fn test_and() {
     AND R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 2);
 }
@@ -87,7 +96,7 @@ fn test_or() {
     OR R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 7);
 }
@@ -99,7 +108,7 @@ fn test_shl() {
     SHL R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 48);
 }
@@ -112,7 +121,7 @@ fn test_shr() {
     SHR R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 1);
 }
@@ -125,7 +134,7 @@ fn test_xor() {
     XOR R1 R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 4);
 }
@@ -136,7 +145,7 @@ fn test_neg() {
     NEG R1
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), -4);
 }
@@ -148,7 +157,7 @@ fn test_add_imm() {
     ADD R1 R1 3
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 5);
 }
@@ -160,7 +169,7 @@ fn test_str() {
     STR R1 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R2), 5);
     assert_eq!(os.cpu.regs.get(&Register::R1), 8000);
@@ -174,7 +183,7 @@ fn test_str_imm() {
         STR R1 7
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 8000);
     assert_eq!(os.cpu.mem.get_num(8000), 7);
@@ -187,7 +196,7 @@ fn test_load() {
         LOAD R2 R1
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 8000);
     assert_eq!(os.cpu.mem.get_num(8000), 7);
@@ -201,7 +210,7 @@ fn test_load_imm() {
         LOAD R2 8000
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 8000);
     assert_eq!(os.cpu.mem.get_num(8000), 7);
@@ -214,7 +223,7 @@ fn test_mov() {
         MOV R2 R1
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 3);
     assert_eq!(os.cpu.regs.get(&Register::R2), 3);
@@ -225,7 +234,7 @@ fn test_mov_imm() {
         MOV R1 3
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 3);
 }
@@ -238,7 +247,7 @@ fn test_tste_yes() {
         TSTE R1 R2 
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::ZR), 1);
 }
@@ -250,7 +259,7 @@ fn test_tste_no() {
         TSTE R1 R2 
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::ZR), 0);
 }
@@ -262,7 +271,7 @@ fn test_tste_imm_yes() {
         TSTE R1 3
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::ZR), 1);
 }
@@ -277,7 +286,7 @@ fn test_tstg_yes() {
         TSTG R1 R2 
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::ZR), 1);
 }
@@ -290,7 +299,7 @@ fn test_tstg_no() {
         TSTG R1 R2 
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::ZR), 0);
 }
@@ -303,7 +312,7 @@ fn test_tstl_yes() {
         TSTL R1 R2 
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::ZR), 1);
 }
@@ -316,7 +325,7 @@ fn test_tstl_no() {
         TSTL R1 R2 
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::ZR), 0);
 }
@@ -330,7 +339,7 @@ fn test_tstl_no() {
 //         SKIP:
 //         HALT
 //     ";
-// 	let mut os = OS::new();
+// 	let mut os = OS::new(MemoryLayout::default());
 // 	let res = os.assemble_and_run_no_std(program);
 // }
 
@@ -343,7 +352,7 @@ fn test_jump() {
         SKIP:
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 3);
 }
@@ -361,7 +370,7 @@ fn test_jump2() {
         END:
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 4);
 }
@@ -376,7 +385,7 @@ fn test_tjump_pos() {
         SKIP:
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 3);
 }
@@ -391,7 +400,7 @@ fn test_tjump_neg() {
         SKIP:
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 4);
 }
@@ -406,7 +415,7 @@ fn test_fjump_pos() {
         SKIP:
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 3);
 }
@@ -421,7 +430,7 @@ fn test_fjump_neg() {
         SKIP:
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 4);
 }
@@ -436,7 +445,7 @@ fn test_push_imm() {
         POP R1
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 2);
 }
@@ -451,7 +460,7 @@ fn test_push() {
         POP R1
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 3);
 }
@@ -467,7 +476,7 @@ fn test_pop() {
         POP R1 
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R2), 4);
     assert_eq!(os.cpu.regs.get(&Register::R1), 1);
@@ -486,7 +495,7 @@ fn test_call_ret_simple() {
         MOV R3 3
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 1);
     assert_eq!(os.cpu.regs.get(&Register::R2), 2);
@@ -517,7 +526,7 @@ fn test_call_ret_with_args_and_retval() {
         POP R1
         HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 3);
 }
@@ -539,7 +548,7 @@ fn test_call_multiple() {
     FOO3:
     RET
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 5);
 }
@@ -591,7 +600,7 @@ fn test_recursion_fiboncci() {
     POP R1
     RET
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 8);
 }
@@ -609,7 +618,7 @@ fn test_data_and_lea() {
     LOAD R2 R2
     HALT
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let _res = os.assemble_and_run_no_std(program);
     assert_eq!(os.cpu.regs.get(&Register::R1), 'e' as i32);
     assert_eq!(os.cpu.regs.get(&Register::R2), 'r' as i32);
@@ -624,7 +633,517 @@ fn test_program_ret_val() {
     STR R1 3
     RET
     ";
-    let mut os = OS::new();
+    let mut os = OS::new(MemoryLayout::default());
     let res = os.assemble_and_run_no_std(program);
     assert_eq!(res, 3);
 }
+
+#[test]
+fn test_load_at_other_addresses() {
+    let program = "
+    .stringz s hi
+    .word table 7 8
+    LEA R1 table
+    ADD R1 R1 1
+    LOAD R2 R1
+    LEA R3 s
+    LOAD R3 R3
+    MOV R4 table-s
+    CALL F
+    HALT
+    F:
+    RET
+    ";
+    let exec = assemble_and_link(vec![program]).unwrap();
+    let mut os = OS::new(MemoryLayout::default());
+    os.load_and_run_at(&exec, 2000, 700);
+    assert_eq!(os.cpu.regs.get(&Register::R1), 704);
+    assert_eq!(os.cpu.regs.get(&Register::R2), 8);
+    assert_eq!(os.cpu.regs.get(&Register::R3), 'h' as i32);
+    assert_eq!(os.cpu.regs.get(&Register::R4), 3);
+}
+
+/// prints c count times, yielding after each char if yields, then exits with status
+fn printer(c: char, count: u32, yields: bool, status: i32) -> String {
+    format!("
+    MOV R4 {}
+    loop:
+    MOV R1 201
+    STR R1 {}
+    MOV R1 200
+    STR R1 1
+    MOV R1 204
+    STR R1 {}
+    SUB R4 R4 1
+    TSTN R4 0
+    TJMP loop
+    ADD R1 BP 2
+    STR R1 {}
+    HALT
+    ", count, c as i32, yields as i32, status)
+}
+
+/// what the program wrote to stdout, which must be a buffer
+fn stdout_of(os: &OS) -> String {
+    String::from_utf8(os.stdout.contents().unwrap().to_vec()).unwrap()
+}
+
+#[test]
+fn test_processes() {
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdout = Output::buffer();
+    let a = os.spawn(&assemble_and_link(vec![&printer('a', 3, true, 7)]).unwrap()).unwrap();
+    let b = os.spawn(&assemble_and_link(vec![&printer('b', 2, true, 9)]).unwrap()).unwrap();
+    assert_ne!(os.processes()[0].code, os.processes()[1].code);
+    assert_ne!(os.processes()[0].stack, os.processes()[1].stack);
+    os.run_processes().unwrap();
+    assert_eq!(stdout_of(&os), "ababa");
+    assert_eq!(os.exit_status(a), Some(7));
+    assert_eq!(os.exit_status(b), Some(9));
+
+    // without yields, processes are switched when their time slice ends
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdout = Output::buffer();
+    os.quantum = 20;
+    os.spawn(&assemble_and_link(vec![&printer('a', 4, false, 0)]).unwrap()).unwrap();
+    os.spawn(&assemble_and_link(vec![&printer('b', 4, false, 0)]).unwrap()).unwrap();
+    os.run_processes().unwrap();
+    assert_eq!(stdout_of(&os), "aabbaabb");
+}
+
+#[test]
+fn test_syscalls() {
+    // sbrk past the end of the heap & unknown calls fail, exit ends the program right away
+    let program = "
+    MOV R1 206
+    STR R1 5000
+    MOV R1 205
+    STR R1 4
+    MOV R1 209
+    LOAD R2 R1
+    MOV R1 205
+    STR R1 99
+    MOV R1 209
+    LOAD R3 R1
+    MOV R1 206
+    STR R1 12
+    MOV R1 205
+    STR R1 1
+    MOV R4 1
+    HALT
+    ";
+    let mut os = OS::new(MemoryLayout::default());
+    let res = os.assemble_and_run_no_std(program);
+    assert_eq!(res, 12);
+    assert_eq!(os.cpu.regs.get(&Register::R2), -1);
+    assert_eq!(os.cpu.regs.get(&Register::R3), -1);
+    assert_eq!(os.cpu.regs.get(&Register::R4), 0);
+}
+
+#[test]
+fn test_filesystem() {
+    // the file is written & read back through the libc wrappers, paths outside of the root fail
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("root");
+    std::fs::create_dir(&root).unwrap();
+    let source = dir.path().join("files.c");
+    std::fs::write(&source, "\
+#include <libc.h>
+int main(){
+    struct FILE* f = fopen(\"notes.txt\", \"w\");
+    fprintf(f, \"n=%d\\n\", 42);
+    fputs(\"second\\n\", f);
+    fclose(f);
+    f = fopen(\"notes.txt\", \"r\");
+    char line[20];
+    while (fgets(line, 20, f)) {
+        puts(line);
+    }
+    fclose(f);
+    if (fopen(\"../files.c\", \"r\") != 0) {
+        return -1;
+    }
+    int fd = open(\"notes.txt\", 0);
+    int size = lseek(fd, 0, 2);
+    close(fd);
+    return size;
+}
+").unwrap();
+    let mut os = OS::with_config(OsConfig { fs_root: Some(root.clone()), ..OsConfig::default() });
+    os.stdout = Output::buffer();
+    let program = os.compile(source.to_str().unwrap());
+    let exec = os.link_with_std(vec![&program]).unwrap();
+    assert_eq!(os.load_and_run(&exec), 12);
+    assert_eq!(stdout_of(&os), "n=42\nsecond\n");
+    assert_eq!(std::fs::read_to_string(root.join("notes.txt")).unwrap(), "n=42\nsecond\n");
+}
+
+#[test]
+fn test_main_args() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("args.c");
+    std::fs::write(&source, "\
+#include <libc.h>
+int main(int argc, char** argv, char** envp){
+    for(int i = 0; i < argc; i++) {
+        puts(*(argv + i));
+        putc(' ');
+    }
+    for(; *envp != 0; envp++) {
+        puts(*envp);
+    }
+    if (*(argv + argc) != 0) {
+        return -1;
+    }
+    return argc;
+}
+").unwrap();
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdout = Output::buffer();
+    os.args = vec!["args".to_string(), "a".to_string(), "bc".to_string()];
+    os.env = vec!["HOME=/".to_string()];
+    let program = os.compile(source.to_str().unwrap());
+    let exec = os.link_with_std(vec![&program]).unwrap();
+    assert_eq!(os.load_and_run(&exec), 3);
+    assert_eq!(stdout_of(&os), "args a bc HOME=/");
+    // without arguments argc is 0 & argv is empty
+    os.stdout = Output::buffer();
+    os.args.clear();
+    os.env.clear();
+    assert_eq!(os.load_and_run(&exec), 0);
+    assert_eq!(stdout_of(&os), "");
+}
+
+#[test]
+fn test_memory_layout() {
+    // the regions move with the layout, malloc finds the heap through the layout registers
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("layout.c");
+    std::fs::write(&source, "\
+#include <libc.h>
+int main(){
+    int* HEAP_START = 214;
+    int* HEAP_END = 215;
+    int* p = malloc(10);
+    *p = 5;
+    if (p < *HEAP_START) {
+        return -1;
+    }
+    return *HEAP_END - *HEAP_START + *p;
+}
+").unwrap();
+    let layout = MemoryLayout { data_size: 200, code_size: 4000, heap_size: 500, stack_size: 1000 };
+    let mut os = OS::new(layout);
+    let program = os.compile(source.to_str().unwrap());
+    let exec = os.link_with_std(vec![&program]).unwrap();
+    assert_eq!(os.load_and_run(&exec), 505);
+    assert_eq!(os.layout().heap(), 4700..5200);
+
+    let layout = MemoryLayout { code_size: 10, ..MemoryLayout::default() };
+    let mut os = OS::new(layout);
+    let size = exec.code.len() as u32;
+    assert_eq!(os.spawn(&exec).unwrap_err(), LoadError::NoRoom { segment: "code", size });
+}
+
+#[test]
+fn test_processes_malloc() {
+    // the heap belongs to the first process that mallocs, malloc returns NULL for the others while it runs
+    let dir = tempfile::tempdir().unwrap();
+    let mut os = OS::new(MemoryLayout::default());
+    os.quantum = 100000; // the first process mallocs before it yields
+    let mut pids = Vec::new();
+    for value in [1, 2] {
+        let source = dir.path().join(format!("malloc{}.c", value));
+        std::fs::write(&source, format!("\
+#include <libc.h>
+int main(){{
+    int* YLD = 204;
+    int* p = malloc(10);
+    if (p == 0) {{
+        return -1;
+    }}
+    *p = {};
+    *YLD = 1;
+    return *p;
+}}
+", value)).unwrap();
+        let program = os.compile(source.to_str().unwrap());
+        let exec = os.link_with_std(vec![&program]).unwrap();
+        pids.push(os.spawn(&exec).unwrap());
+    }
+    os.run_processes().unwrap();
+    assert_eq!(os.exit_status(pids[0]), Some(1));
+    assert_eq!(os.exit_status(pids[1]), Some(-1));
+}
+
+#[test]
+#[should_panic(expected = "words of code overflow its region, which has room for 3")]
+fn test_memory_layout_overflow() {
+    let layout = MemoryLayout { code_size: 3, ..MemoryLayout::default() };
+    let mut os = OS::new(layout);
+    os.assemble_and_run_no_std("
+    MOV R1 1
+    MOV R2 2
+    ADD R1 R1 R2
+    HALT
+    ");
+}
+
+#[test]
+fn test_run_limits() {
+    let program = "
+    MOV R1 0
+    CALL spin
+    HALT
+    spin:
+    ADD R1 R1 1
+    JUMP spin
+    ";
+    let exec = assemble_and_link(vec![program]).unwrap();
+    let mut os = OS::new(MemoryLayout::default());
+    os.limits = RunLimits { max_instructions: Some(100), timeout: None };
+    let e = match os.run(&exec).status {
+        RunStatus::LimitExceeded(e) => e,
+        status => panic!("the program wasn't stopped: {:?}", status),
+    };
+    assert_eq!(e.limit, Limit::Instructions(100));
+    assert_eq!(e.location.pid, 1);
+    let (function, offset) = e.location.function.unwrap();
+    assert_eq!(function, "spin");
+    assert!(offset < 2);
+    assert_eq!(e.location.addr, 1003 + offset);
+    // the limit counts the instructions of each run from the start
+    assert!(matches!(os.run(&exec).status, RunStatus::LimitExceeded(_)));
+    assert_eq!(os.cpu.regs.get(&Register::R1), 49);
+
+    os.limits = RunLimits { max_instructions: None, timeout: Some(std::time::Duration::from_millis(50)) };
+    match os.run(&exec).status {
+        RunStatus::LimitExceeded(e) => assert!(e.to_string().starts_with("timeout of 50ms exceeded at spin+")),
+        status => panic!("the program wasn't stopped: {:?}", status),
+    }
+    os.limits = RunLimits::default();
+    assert_eq!(os.run(&assemble_and_link(vec!["HALT"]).unwrap()).status, RunStatus::Exited(-1));
+}
+
+#[test]
+fn test_run_outcome() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("outcome.c");
+    std::fs::write(&source, "\
+#include <libc.h>
+int depth(int n){
+    if (n == 0) {
+        return 0;
+    }
+    return depth(n - 1);
+}
+int main(){
+    int* p = malloc(20);
+    puts(\"hi\");
+    return depth(10);
+}
+").unwrap();
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdout = Output::buffer();
+    let program = os.compile(source.to_str().unwrap());
+    let exec = os.link_with_std(vec![&program]).unwrap();
+    let outcome = os.run(&exec);
+    assert_eq!(outcome.status, RunStatus::Exited(0));
+    assert_eq!(outcome.stdout, b"hi");
+    assert!(outcome.instructions > 100);
+    // every call of depth takes more than its arg, return value, return address & BP
+    assert!(outcome.peak_stack_depth > 10 * 4);
+    // the allocated block's size & the free block after it are written
+    assert!(outcome.peak_heap_usage > 20);
+    assert!(outcome.peak_heap_usage < 40);
+
+    let program = "
+    MOV R1 1
+    MOV R2 0
+    CALL F
+    HALT
+    F:
+    DIV R1 R1 R2
+    RET
+    ";
+    let outcome = os.run(&assemble_and_link(vec![program]).unwrap());
+    assert_eq!(outcome.exit_status(), None);
+    assert_eq!(outcome.instructions, 4);
+    let fault = match outcome.status {
+        RunStatus::Fault(fault) => fault,
+        status => panic!("the program didn't fault: {:?}", status),
+    };
+    assert_eq!(fault.fault, Fault::DivisionByZero);
+    assert_eq!(fault.location.function, Some(("F".to_string(), 0)));
+    // the caller's frame is at its CALL, before any label
+    assert_eq!(format_backtrace(&fault.backtrace), "#0 F+0 (address 1004)\n#1 ?? (address 1002)\n");
+
+    // a faulting process doesn't stop the others
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdout = Output::buffer();
+    os.spawn(&assemble_and_link(vec!["LOAD R1 123456\nHALT"]).unwrap()).unwrap();
+    let b = os.spawn(&assemble_and_link(vec![&printer('b', 2, true, 9)]).unwrap()).unwrap();
+    os.run_processes().unwrap();
+    assert!(matches!(&os.processes()[0].state, ProcessState::Faulted(fault) if fault.fault == Fault::InvalidAccess(123456)));
+    assert_eq!(os.exit_status(b), Some(9));
+}
+
+#[test]
+fn test_core_dump() {
+    let program = "
+    .word x 42
+    MAIN:
+    MOV R1 5
+    LEA R3 x
+    CALL F
+    HALT
+    F:
+    CALL G
+    RET
+    G:
+    LOAD R2 123456
+    RET
+    ";
+    let dir = tempfile::tempdir().unwrap();
+    let mut os = OS::with_config(OsConfig { core_dir: Some(dir.path().to_path_buf()), ..OsConfig::default() });
+    os.stdout = Output::buffer();
+    let outcome = os.run(&assemble_and_link(vec![program]).unwrap());
+    assert!(matches!(outcome.status, RunStatus::Fault(_)));
+    let core = load_core(dir.path().join("core.1").to_str().unwrap()).unwrap();
+    assert_eq!(core.fault, Fault::InvalidAccess(123456));
+    assert_eq!(core.command(&["info"]).unwrap(), "process 1 faulted: invalid memory access: 123456 at G+0 (address 1006) in process 1");
+    assert_eq!(core.command(&["reg", "R1"]).unwrap(), "5");
+    assert_eq!(core.command(&["reg", "IR"]).unwrap(), "1006");
+    assert!(core.command(&["reg", "R9"]).is_err());
+    assert_eq!(core.command(&["mem", "x"]).unwrap(), "500 <x>: 42");
+    assert_eq!(core.command(&["mem", "1005", "3"]).unwrap(), "1005: RET\n1006: LOAD R2 123456\n1007: RET");
+    assert_eq!(core.command(&["mem", "4294967295"]).unwrap(), "4294967295: ??");
+    assert_eq!(core.command(&["mem", "4294967295", "2"]).unwrap_err(), "invalid count: 2");
+    assert_eq!(core.command(&["bt"]).unwrap(), "#0 G+0 (address 1006)\n#1 F+0 (address 1004)\n#2 MAIN+2 (address 1002)");
+
+    // without a core directory nothing is written
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdout = Output::buffer();
+    os.run(&assemble_and_link(vec![program]).unwrap());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("fib.c");
+    std::fs::write(&source, "\
+int fib(int n){
+    if (n < 2) {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+int main(){
+    return fib(10);
+}
+").unwrap();
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdout = Output::buffer();
+    os.profile = Some(Profile::new());
+    let program = os.compile(source.to_str().unwrap());
+    let exec = os.link_with_std(vec![&program]).unwrap();
+    let outcome = os.run(&exec);
+    assert_eq!(outcome.status, RunStatus::Exited(55));
+    let profile = os.profile.as_ref().unwrap();
+    let calls = profile.call_counts();
+    assert!(calls.contains(&("main".to_string(), "fib".to_string(), 1)));
+    assert!(calls.contains(&("fib".to_string(), "fib".to_string(), 176)));
+    let functions = profile.functions();
+    assert_eq!(functions.iter().map(|function| function.self_instructions).sum::<u64>(), outcome.instructions);
+    let fib = &functions[0];
+    assert_eq!((fib.function.as_str(), fib.calls), ("fib", 177));
+    assert_eq!(fib.inclusive_instructions, fib.self_instructions);
+    let main = functions.iter().find(|function| function.function == "main").unwrap();
+    assert_eq!(main.inclusive_instructions, main.self_instructions + fib.self_instructions);
+    // fib(10) recurses 10 deep
+    let folded = profile.folded_stacks();
+    assert!(folded.lines().any(|line| line.starts_with(&format!("main{} ", ";fib".repeat(10)))));
+    assert!(!folded.lines().any(|line| line.starts_with(&format!("main{} ", ";fib".repeat(11)))));
+    assert_eq!(folded.lines().map(|line| line.rsplit(' ').next().unwrap().parse::<u64>().unwrap()).sum::<u64>(), outcome.instructions);
+}
+
+#[test]
+fn test_fault_in_static_function() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("static.c");
+    std::fs::write(&source, "\
+static int load(int* p){
+    return *p;
+}
+int main(){
+    int* p = 123456;
+    return load(p);
+}
+").unwrap();
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdout = Output::buffer();
+    let program = os.compile(source.to_str().unwrap());
+    let exec = os.link_with_std(vec![&program]).unwrap();
+    let fault = match os.run(&exec).status {
+        RunStatus::Fault(fault) => fault,
+        status => panic!("the program didn't fault: {:?}", status),
+    };
+    assert_eq!(fault.fault, Fault::InvalidAccess(123456));
+    let functions: Vec<&str> = fault.backtrace.iter().map(|frame| frame.function.as_ref().unwrap().0.as_str()).collect();
+    assert_eq!(functions, vec!["load", "main"]);
+    assert_eq!(fault.location.function.unwrap().0, "load");
+}
+
+/// calls syscall num with the given args, its result is left in reg
+fn syscall(num: i32, args: [i32; 3], reg: &str) -> String {
+    format!("
+    MOV R1 206
+    STR R1 {}
+    MOV R1 207
+    STR R1 {}
+    MOV R1 208
+    STR R1 {}
+    MOV R1 205
+    STR R1 {}
+    LOAD {} 209
+    ", args[0], args[1], args[2], num, reg)
+}
+
+#[test]
+fn test_syscall_bad_buffers() {
+    let program = [
+        // write from an address that was never written
+        syscall(3, [1, 123456, 3], "R2"),
+        // read into the code
+        syscall(2, [0, 1000, 3], "R3"),
+        // read past the end of the heap, cut at the heap's end
+        syscall(2, [0, 5998, 1000000000], "R4"),
+        "HALT".to_string(),
+    ].concat();
+    let mut os = OS::new(MemoryLayout::default());
+    os.stdin = Input::buffer("abc");
+    os.stdout = Output::buffer();
+    os.run(&assemble_and_link(vec![&program]).unwrap());
+    assert_eq!(os.cpu.regs.get(&Register::R2), -1);
+    assert_eq!(os.cpu.regs.get(&Register::R3), -1);
+    assert_eq!(os.cpu.regs.get(&Register::R4), 2);
+    assert_eq!(os.cpu.mem.get_num(5999), 'b' as i32);
+    assert!(os.cpu.mem.try_get(6000).is_err());
+}
+
+#[test]
+fn test_sbrk_overflow() {
+    // increments that overflow the break fail & leave it as it was
+    let program = [
+        syscall(4, [i32::MAX, 0, 0], "R2"),
+        syscall(4, [i32::MIN, 0, 0], "R3"),
+        syscall(4, [0, 0, 0], "R4"),
+        "HALT".to_string(),
+    ].concat();
+    let mut os = OS::new(MemoryLayout::default());
+    os.run(&assemble_and_link(vec![&program]).unwrap());
+    assert_eq!(os.cpu.regs.get(&Register::R2), -1);
+    assert_eq!(os.cpu.regs.get(&Register::R3), -1);
+    assert_eq!(os.cpu.regs.get(&Register::R4), 4000);
+}